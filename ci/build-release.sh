@@ -13,6 +13,7 @@ if [[ "$(uname -s)" == "Linux" ]]; then
     docker run --rm -it -v "$(pwd)":/home/rust/src ekidd/rust-musl-builder cargo build --release
     zip -j target/gh-release/"$1"-"$2".zip target/x86_64-unknown-linux-musl/release/"$1"
 else
-    cargo build --release
+    # The secret store needs libdbus on Linux, which the static musl build can't link, so it's only built in elsewhere
+    cargo build --release --features keyring
     zip -j target/gh-release/"$1"-"$2".zip target/release/"$1"
 fi
@@ -0,0 +1,266 @@
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use hyper;
+use reqwest::{self, Client, StatusCode, Response, RequestBuilder};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, USER_AGENT};
+use serde::Serialize;
+use time;
+
+use options::{Authentication, Credentials};
+use repository::Service;
+use network::Network;
+use prompt;
+use interrupt;
+use verbose;
+use errors::*;
+
+const USER_AGENT_VALUE: &str = concat!("ghcl/", env!("CARGO_PKG_VERSION"));
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_API_RETRIES: u32 = 3;
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+
+const MAX_RATE_LIMIT_WAIT: u64 = 60;
+
+const SECONDARY_RATE_LIMIT_WAIT: u64 = 60;
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+/// Exponential backoff, with up to half of the delay randomized
+pub fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_BASE_DELAY_MS << attempt.min(10);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.subsec_nanos()).unwrap_or(0);
+    Duration::from_millis(delay / 2 + u64::from(nanos) % (delay / 2 + 1))
+}
+
+/// `is_http` is also true for requests which couldn't be built, which are never worth retrying
+pub fn is_connection_error(err: &reqwest::Error) -> bool {
+    if err.is_timeout() {
+        return true;
+    }
+    match err.get_ref() {
+        Some(inner) => match inner.downcast_ref::<hyper::Error>() {
+            Some(hyper_err) => !hyper_err.is_user() && !hyper_err.is_parse(),
+            None => inner.is::<io::Error>(),
+        },
+        None => false,
+    }
+}
+
+fn is_transient(err: &Error) -> bool {
+    match *err.kind() {
+        ErrorKind::Reqwest(ref err) => is_connection_error(err),
+        _ => false,
+    }
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name).and_then(|value| value.to_str().ok()).and_then(|value| value.trim().parse().ok())
+}
+
+pub fn response_date(headers: &HeaderMap) -> Option<u64> {
+    let date = headers.get("Date")?.to_str().ok()?;
+    let date = time::strptime(date.trim(), "%a, %d %b %Y %H:%M:%S GMT").ok()?.to_timespec();
+    if date.sec < 0 { None } else { Some(date.sec as u64) }
+}
+
+/// How many seconds to wait if the response says the rate limit was hit
+pub fn rate_limit_wait(status: StatusCode, headers: &HeaderMap, now: u64) -> Option<u64> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    if let Some(retry_after) = header_u64(headers, "Retry-After") {
+        return Some(retry_after);
+    }
+    match (header_u64(headers, "X-RateLimit-Remaining"), header_u64(headers, "X-RateLimit-Reset")) {
+        (Some(0), Some(reset)) => Some(reset.saturating_sub(now) + 1),
+        (Some(0), None) => Some(SECONDARY_RATE_LIMIT_WAIT),
+        _ if status == StatusCode::TOO_MANY_REQUESTS => Some(SECONDARY_RATE_LIMIT_WAIT),
+        _ => None,
+    }
+}
+
+pub fn http_client(service: Service, network: &Network) -> Result<Client> {
+    let mut headers = HeaderMap::new();
+    headers.insert(USER_AGENT, HeaderValue::from_static(USER_AGENT_VALUE));
+    match service {
+        Service::GitHub => {
+            headers.insert(ACCEPT, HeaderValue::from_static("application/vnd.github+json"));
+            headers.insert("X-GitHub-Api-Version", HeaderValue::from_static("2022-11-28"));
+        }
+    }
+    Ok(network.client_builder()?
+        .default_headers(headers)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()?)
+}
+
+fn authenticate(request: RequestBuilder, authentication: &Authentication) -> RequestBuilder {
+    match *authentication {
+        Authentication::Token { ref token, .. } => request.bearer_auth(token.expose()),
+        Authentication::Basic { ref username, ref password } => request.basic_auth(username, Some(password.expose())),
+    }
+}
+
+fn ask_for_otp() -> Result<Option<String>> {
+    if !prompt::is_interactive() {
+        return Ok(None);
+    }
+    Ok(Some(prompt::ask_for("Two-factor authentication code: ", false)?))
+}
+
+pub struct Api {
+    api_url: String,
+    http_client: Client,
+    credentials: Rc<Credentials>,
+    retries: u32,
+    quiet: bool,
+    otp: RefCell<Option<String>>,
+    ask_for_otp: fn() -> Result<Option<String>>,
+}
+
+impl Api {
+    pub fn new(service: Service, network: &Network, credentials: Rc<Credentials>, retries: u32, quiet: bool) -> Result<Api> {
+        Api::with_url(service, service.api_url(), network, credentials, retries, quiet)
+    }
+
+    pub fn with_url(service: Service, api_url: &str, network: &Network, credentials: Rc<Credentials>, retries: u32, quiet: bool) -> Result<Api> {
+        Ok(Api {
+            api_url: api_url.to_string(),
+            http_client: http_client(service, network)?,
+            credentials: credentials,
+            retries: retries,
+            quiet: quiet,
+            otp: RefCell::new(None),
+            ask_for_otp: ask_for_otp,
+        })
+    }
+
+    /// Returns `None` when there's no one to ask, which fails with `OTPRequired`
+    pub fn set_otp_prompt(&mut self, ask_for_otp: fn() -> Result<Option<String>>) {
+        self.ask_for_otp = ask_for_otp;
+    }
+
+    pub fn authentication(&self) -> Result<Authentication> {
+        self.credentials.get()
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.api_url, path)
+    }
+
+    /// Sent anonymously unless the credentials are known without prompting, and only prompted for if it isn't found
+    pub fn get(&self, path: &str) -> Result<Response> {
+        if self.credentials.get_without_prompting()?.is_some() {
+            return self.get_authenticated(path);
+        }
+        verbose!(verbose::DEBUG, "No credentials without prompting, so sending the request anonymously");
+        let res = self.send(|| self.http_client.get(&self.url(path)), true, false)?;
+        if (res.status() == StatusCode::NOT_FOUND || res.status() == StatusCode::UNAUTHORIZED) && prompt::is_interactive() {
+            return self.get_authenticated(path);
+        }
+        Ok(res)
+    }
+
+    pub fn get_authenticated(&self, path: &str) -> Result<Response> {
+        self.send(|| self.http_client.get(&self.url(path)), true, true)
+    }
+
+    pub fn post<T: Serialize>(&self, path: &str, body: &T, idempotent: bool) -> Result<Response> {
+        self.send(|| self.http_client.post(&self.url(path)).json(body), idempotent, true)
+    }
+
+    fn send<F: Fn() -> RequestBuilder>(&self, build: F, idempotent: bool, authenticated: bool) -> Result<Response> {
+        let mut waited = 0;
+        let mut attempt = 0;
+        loop {
+            let retry = idempotent && attempt < self.retries;
+            let sent = if authenticated { self.send_authenticated(&build) } else { self.execute(build()) };
+            let res = match sent {
+                Ok(ref res) if retry && res.status().is_server_error() => {
+                    self.wait_to_retry(attempt, &res.status().to_string())?;
+                    attempt += 1;
+                    continue;
+                }
+                Ok(res) => res,
+                Err(ref err) if retry && is_transient(err) => {
+                    self.wait_to_retry(attempt, &err.to_string())?;
+                    attempt += 1;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let wait = match rate_limit_wait(res.status(), res.headers(), unix_now()) {
+                Some(wait) => wait,
+                None => return Ok(res),
+            };
+            if waited + wait > MAX_RATE_LIMIT_WAIT {
+                Err(ErrorKind::RateLimited(unix_now() + wait))?
+            }
+            if !self.quiet {
+                writeln!(io::stderr(), "API rate limit hit, waiting {} seconds", wait).ok();
+            }
+            interrupt::sleep(Duration::from_secs(wait))?;
+            waited += wait;
+        }
+    }
+
+    fn wait_to_retry(&self, attempt: u32, reason: &str) -> Result<()> {
+        let delay = backoff(attempt);
+        if !self.quiet {
+            writeln!(io::stderr(), "API request failed ({}), retrying in {:.1} seconds", reason, delay.as_millis() as f64 / 1000.0).ok();
+        }
+        interrupt::sleep(delay)
+    }
+
+    fn execute(&self, request: RequestBuilder) -> Result<Response> {
+        let request = request.build()?;
+        let description = format!("{} {}", request.method(), request.url());
+        let start = Instant::now();
+        let res = match self.http_client.execute(request) {
+            Ok(res) => res,
+            Err(err) => {
+                verbose!(verbose::DEBUG, "{} failed after {:.2}s: {}", description, start.elapsed().as_millis() as f64 / 1000.0, err);
+                return Err(err.into());
+            }
+        };
+        verbose!(verbose::DEBUG, "{} -> {} in {:.2}s (rate limit: {} remaining)", description, res.status(),
+            start.elapsed().as_millis() as f64 / 1000.0,
+            header_u64(res.headers(), "X-RateLimit-Remaining").map_or("unknown".to_string(), |remaining| remaining.to_string()));
+        Ok(res)
+    }
+
+    /// Prompts for a two-factor code if GitHub asks for one, and keeps it for later requests
+    fn send_authenticated<F: Fn() -> RequestBuilder>(&self, build: F) -> Result<Response> {
+        let authentication = self.credentials.get()?;
+        let res = self.execute(self.with_otp(authenticate(build(), &authentication)))?;
+        let otp_required = res.status() == StatusCode::UNAUTHORIZED && res.headers().get("X-GitHub-OTP")
+            .and_then(|value| value.to_str().ok())
+            .map_or(false, |value| value.starts_with("required"));
+        if !otp_required {
+            return Ok(res);
+        }
+        let otp = match (self.ask_for_otp)()? {
+            Some(otp) => otp,
+            None => Err(ErrorKind::OTPRequired)?,
+        };
+        *self.otp.borrow_mut() = Some(otp.trim().to_string());
+        self.execute(self.with_otp(authenticate(build(), &authentication)))
+    }
+
+    fn with_otp(&self, request: RequestBuilder) -> RequestBuilder {
+        match *self.otp.borrow() {
+            Some(ref otp) => request.header("X-GitHub-OTP", otp.as_str()),
+            None => request,
+        }
+    }
+}
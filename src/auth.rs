@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use reqwest::header::ACCEPT;
+
+use options::{Authentication, AuthAction, AuthOptions, Credentials};
+use credentials::{CredentialStore, Storage, StoredToken};
+use repository::Service;
+use secrets::Secret;
+use api::{self, Api};
+use interrupt;
+use output::{self, AuthStatus, OutputFormat};
+use errors::*;
+
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+pub struct DeviceFlow<'a> {
+    pub device_code_url: &'a str,
+    pub token_url: &'a str,
+    pub client_id: &'a str,
+    pub scope: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: Option<Secret>,
+    error: Option<String>,
+    error_description: Option<String>,
+    interval: Option<u64>,
+}
+
+impl<'a> DeviceFlow<'a> {
+    pub fn for_service(service: Service, client_id: &'a str) -> DeviceFlow<'a> {
+        match service {
+            Service::GitHub => DeviceFlow {
+                device_code_url: "https://github.com/login/device/code",
+                token_url: "https://github.com/login/oauth/access_token",
+                client_id: client_id,
+                scope: "repo",
+            },
+        }
+    }
+
+    pub fn login<F: FnOnce(&str, &str)>(&self, http_client: &Client, show_code: F) -> Result<Secret> {
+        let code: DeviceCode = http_client.post(self.device_code_url)
+            .header(ACCEPT, "application/json")
+            .form(&[("client_id", self.client_id), ("scope", self.scope)])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        show_code(&code.verification_uri, &code.user_code);
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+        loop {
+            interrupt::sleep(interval)?;
+            if Instant::now() > deadline {
+                Err(ErrorKind::DeviceCodeExpired)?
+            }
+            let res: TokenResponse = http_client.post(self.token_url)
+                .header(ACCEPT, "application/json")
+                .form(&[("client_id", self.client_id), ("device_code", code.device_code.as_str()), ("grant_type", DEVICE_GRANT_TYPE)])
+                .send()?
+                .json()?;
+            if let Some(token) = res.access_token {
+                return Ok(token);
+            }
+            match res.error.as_ref().map(|s| s.as_str()) {
+                Some("authorization_pending") => {}
+                Some("slow_down") => {
+                    interval = res.interval.map(Duration::from_secs).unwrap_or(interval + Duration::from_secs(5));
+                }
+                Some("expired_token") => Err(ErrorKind::DeviceCodeExpired)?,
+                Some("access_denied") => Err(ErrorKind::AuthorizationDenied)?,
+                _ => Err(ErrorKind::APIError(res.error_description.or_else(|| res.error.clone()).unwrap_or_else(|| "no access token in response".into())))?,
+            }
+        }
+    }
+}
+
+fn login(options: &AuthOptions) -> Result<()> {
+    let mut stderr = io::stderr();
+    let client_id = options.oauth_client_id.as_ref().ok_or(ErrorKind::NoOAuthClientId(options.service))?;
+    let http_client = api::http_client(options.service, &options.network)?;
+    let token = DeviceFlow::for_service(options.service, client_id).login(&http_client, |uri, user_code| {
+        writeln!(stderr, "First copy your one-time code: {}", user_code).ok();
+        writeln!(stderr, "Then open {} in your browser, and enter the code to authorize ghcl", uri).ok();
+        writeln!(stderr, "Waiting for authorization...").ok();
+    }).chain_err(|| "Failed to log in")?;
+    let credentials = Credentials::known(Authentication::Token { username: None, token: token.clone() });
+    let username = options.service.get_username(&Api::new(options.service, &options.network, Rc::new(credentials), options.api_retries, false)?)
+        .chain_err(|| "Failed to look up username")?;
+    let mut store = CredentialStore::load()?;
+    let storage = store.set(options.service, options.profile.as_ref().map(|s| s.as_str()), StoredToken { username: Some(username.clone()), token: token });
+    store.save()?;
+    writeln!(io::stderr(), "Logged in to {:?} as {}", options.service, username).ok();
+    if storage == Storage::File {
+        writeln!(io::stderr(), "Warning: no OS secret store is available, so the token was saved in plaintext in {}", CredentialStore::path()?.display()).ok();
+    }
+    Ok(())
+}
+
+fn status(options: &AuthOptions, output_format: OutputFormat) -> Result<()> {
+    let stored = CredentialStore::load()?.get(options.service, options.profile.as_ref().map(|s| s.as_str()))?.ok_or(ErrorKind::NotLoggedIn(options.service))?;
+    let credentials = Credentials::known(Authentication::Token { username: None, token: stored.token });
+    let username = options.service.get_username(&Api::new(options.service, &options.network, Rc::new(credentials), options.api_retries, false)?).chain_err(|| "Stored token is not valid (log in again with `ghcl auth login`)")?;
+    if output_format == OutputFormat::Json {
+        return output::print_json(&AuthStatus {
+            service: options.service,
+            profile: options.profile.as_ref().map(|s| s.as_str()),
+            username: &username,
+        });
+    }
+    writeln!(io::stdout(), "Logged in to {:?} as {}", options.service, username)?;
+    Ok(())
+}
+
+fn logout(options: &AuthOptions) -> Result<()> {
+    let mut store = CredentialStore::load()?;
+    if !store.remove(options.service, options.profile.as_ref().map(|s| s.as_str()))? {
+        Err(ErrorKind::NotLoggedIn(options.service))?
+    }
+    store.save()?;
+    writeln!(io::stderr(), "Logged out of {:?}", options.service).ok();
+    Ok(())
+}
+
+pub fn run(options: &AuthOptions, output_format: OutputFormat) -> Result<()> {
+    match options.action {
+        AuthAction::Login => login(options),
+        AuthAction::Status => status(options, output_format),
+        AuthAction::Logout => logout(options),
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+use serde_yaml;
+
+use options::config_dir;
+use repository::Service;
+use secrets::{self, Secret};
+use verbose;
+use errors::*;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub username: Option<String>,
+    pub token: Secret,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    SecretStore,
+    File,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    username: Option<String>,
+    /// Only set if the OS secret store wasn't available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<Secret>,
+}
+
+/// Kept separate from the config, so that the config can be shared
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CredentialStore {
+    #[serde(default)]
+    tokens: HashMap<Service, Entry>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, HashMap<Service, Entry>>,
+}
+
+impl CredentialStore {
+    pub fn path() -> Result<PathBuf> {
+        let mut path = config_dir()?;
+        path.push("credentials.yml");
+        Ok(path)
+    }
+
+    pub fn load() -> Result<CredentialStore> {
+        match File::open(CredentialStore::path()?) {
+            Ok(file) => Ok(serde_yaml::from_reader(file)?),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(CredentialStore::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = CredentialStore::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        Ok(serde_yaml::to_writer(options.open(path)?, self)?)
+    }
+
+    fn entries(&mut self, profile: Option<&str>) -> &mut HashMap<Service, Entry> {
+        match profile {
+            Some(profile) => self.profiles.entry(profile.into()).or_insert_with(HashMap::new),
+            None => &mut self.tokens,
+        }
+    }
+
+    pub fn get(&self, service: Service, profile: Option<&str>) -> Result<Option<StoredToken>> {
+        let entry = match profile {
+            Some(profile) => self.profiles.get(profile).and_then(|entries| entries.get(&service)),
+            None => self.tokens.get(&service),
+        };
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let token = match entry.token {
+            Some(ref token) => token.clone(),
+            // Without a D-Bus session, for instance, the other credential sources are tried instead
+            None => match secrets::get(service, profile) {
+                Ok(Some(token)) => token,
+                Ok(None) => {
+                    verbose!(verbose::DEBUG, "No token for {} in the OS secret store", service.host());
+                    return Ok(None);
+                }
+                Err(err) => {
+                    verbose!(verbose::DEBUG, "Couldn't read the saved token: {}", err);
+                    return Ok(None);
+                }
+            },
+        };
+        Ok(Some(StoredToken { username: entry.username.clone(), token: token }))
+    }
+
+    pub fn find(&self, service: Service, profile: Option<&str>) -> Result<Option<StoredToken>> {
+        match self.get(service, profile)? {
+            Some(stored) => Ok(Some(stored)),
+            None if profile.is_some() => self.get(service, None),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set(&mut self, service: Service, profile: Option<&str>, token: StoredToken) -> Storage {
+        let (secret, storage) = match secrets::set(service, profile, &token.token) {
+            Ok(()) => (None, Storage::SecretStore),
+            Err(_) => (Some(token.token), Storage::File),
+        };
+        self.entries(profile).insert(service, Entry { username: token.username, token: secret });
+        storage
+    }
+
+    pub fn remove(&mut self, service: Service, profile: Option<&str>) -> Result<bool> {
+        let removed = self.entries(profile).remove(&service);
+        if let Some(profile) = profile {
+            if self.profiles.get(profile).map_or(false, HashMap::is_empty) {
+                self.profiles.remove(profile);
+            }
+        }
+        match removed {
+            Some(entry) => {
+                if entry.token.is_none() {
+                    secrets::delete(service, profile)?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GhHost {
+    oauth_token: Option<Secret>,
+    user: Option<String>,
+    /// Since gh 2.40, which can log in to more than one account per host
+    #[serde(default)]
+    users: HashMap<String, Option<GhUser>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GhUser {
+    oauth_token: Option<Secret>,
+}
+
+fn gh_hosts_path() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("GH_CONFIG_DIR") {
+        return Some(PathBuf::from(dir).join("hosts.yml"));
+    }
+    let config_dir = if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir).join("gh")
+    } else if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?).join("GitHub CLI")
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".config").join("gh")
+    };
+    Some(config_dir.join("hosts.yml"))
+}
+
+/// Newer versions of gh keep the token in the OS secret store instead, which isn't read
+pub fn gh_cli_token(service: Service) -> Result<Option<StoredToken>> {
+    let path = match gh_hosts_path() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let hosts = match fs::read_to_string(&path) {
+        Ok(hosts) => hosts,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    match gh_hosts_token(&hosts, service) {
+        Ok(token) => Ok(token),
+        Err(err) => {
+            verbose!(verbose::DEBUG, "Couldn't read gh CLI credentials from {}: {}", path.display(), err);
+            Ok(None)
+        },
+    }
+}
+
+/// The active account's token, from the contents of gh's `hosts.yml`
+pub fn gh_hosts_token(hosts: &str, service: Service) -> Result<Option<StoredToken>> {
+    let mut hosts: HashMap<String, GhHost> = serde_yaml::from_str(hosts)?;
+    let GhHost { oauth_token, user, mut users } = match hosts.remove(service.host()) {
+        Some(host) => host,
+        None => return Ok(None),
+    };
+    let token = oauth_token.or_else(|| user.as_ref().and_then(|user| users.remove(user)).and_then(|account| account).and_then(|account| account.oauth_token));
+    Ok(token.map(|token| StoredToken { username: user, token: token }))
+}
@@ -8,6 +8,21 @@ use serde_yaml;
 use regex;
 use reqwest;
 
+use api;
+use interrupt;
+use repository::Service;
+
+/// Documented in the README
+pub mod exit_code {
+    pub const OTHER: i32 = 2;
+    pub const OPTIONS: i32 = 3;
+    pub const AUTHENTICATION: i32 = 4;
+    pub const API: i32 = 5;
+    pub const FORK_TIMED_OUT: i32 = 6;
+    pub const NETWORK: i32 = 7;
+    pub const FILESYSTEM_CONFLICT: i32 = 8;
+}
+
 error_chain! {
     errors {
         ConfigTrackNoSetup {
@@ -19,6 +34,18 @@ error_chain! {
         RemoteNotNamed {
             description("upstream remote does not have a name (or was not valid UTF-8)")
         }
+        UnknownProfile(name: String) {
+            description("no such profile in the config")
+            display("no profile named {} in the config", name)
+        }
+        InvalidProxy(url: String) {
+            description("invalid proxy URL")
+            display("invalid proxy URL: {}", url)
+        }
+        InvalidCABundle(path: String) {
+            description("failed to load the CA bundle")
+            display("failed to load the CA bundle {}", path)
+        }
         FailedToParseRepository {
             description("failed to parse the repository name")
         }
@@ -26,13 +53,62 @@ error_chain! {
             description("fork timed out (new forked repository not cloneable)")
             display("fork timed out (new forked repository not cloneable in {} seconds)", wait)
         }
-        MissingKey(key: &'static str) {
-            description("received JSON missing key")
-            display("received JSON missing key: {}", key)
+        MalformedResponse(reason: String) {
+            description("received an API response which couldn't be parsed")
+            display("received an API response which couldn't be parsed: {}", reason)
+        }
+        AuthenticationFailed(message: String) {
+            description("authentication failed")
+            display("authentication failed: {}", message)
+        }
+        InsufficientPermissions(missing: String) {
+            description("the credentials don't have the permissions needed")
+            display("the credentials don't have the permissions needed (missing: {})", missing)
+        }
+        SshAuthenticationFailed(url: String) {
+            description("SSH authentication failed (no key from the agent or key files was accepted)")
+            display("SSH authentication failed for {} (no key from the agent or key files was accepted)", url)
+        }
+        HostKeyMismatch(host: String, fingerprint: String) {
+            description("the SSH host key doesn't match the known key (someone may be intercepting the connection)")
+            display("the SSH host key for {} ({}) doesn't match the known key (someone may be intercepting the connection)", host, fingerprint)
+        }
+        UnknownHostKey(host: String, fingerprint: String) {
+            description("the SSH host key is unknown, and wasn't trusted")
+            display("the SSH host key for {} ({}) is unknown, and wasn't trusted (connect with ssh first to add it to known_hosts)", host, fingerprint)
+        }
+        MissingCredentials(service: Service) {
+            description("missing credentials, and not running interactively")
+            display("missing credentials for service {:?} (and not running interactively, so not prompting for them)", service)
+        }
+        OTPRequired {
+            description("a two-factor authentication code is required, but ghcl isn't running interactively (use a token instead)")
+        }
+        DeviceCodeExpired {
+            description("the login code expired before it was entered (try logging in again)")
+        }
+        AuthorizationDenied {
+            description("authorization was denied")
+        }
+        NoOAuthClientId(service: Service) {
+            description("no OAuth client ID configured")
+            display("no OAuth client ID configured for {:?} (set oauth_client_id in the config or pass --client-id)", service)
+        }
+        NotLoggedIn(service: Service) {
+            description("not logged in")
+            display("not logged in to {:?} (log in with `ghcl auth login`)", service)
+        }
+        PasswordCommandFailed(command: String, reason: String) {
+            description("password command failed")
+            display("password command `{}` failed: {}", command, reason)
         }
-        MalformedKey(key: &'static str) {
-            description("received JSON with malformed key (expected string)")
-            display("received JSON malformed key (expected string): {}", key)
+        Keyring(reason: String) {
+            description("the OS secret store failed")
+            display("the OS secret store failed: {}", reason)
+        }
+        RateLimited(reset_at: u64) {
+            description("the API rate limit was exceeded")
+            display("the API rate limit was exceeded (it resets in {} seconds)", reset_at.saturating_sub(::api::unix_now()))
         }
         APIError(message: String) {
             description("API error")
@@ -40,7 +116,18 @@ error_chain! {
         }
         RawAPIError(json: serde_json::Value) {
             description("unparsable API error")
-            display("unparsable API error: {:?}", json)
+            display("unparsable API error: {}", json)
+        }
+        Interrupted {
+            description("interrupted by user")
+        }
+        ClonePathExists(path: String) {
+            description("clone path already exists and is not an empty directory")
+            display("clone path {} already exists and is not an empty directory", path)
+        }
+        NothingToResume(path: String) {
+            description("no interrupted or failed run to resume")
+            display("no interrupted or failed run to resume in {}", path)
         }
     }
     foreign_links {
@@ -53,3 +140,90 @@ error_chain! {
         ParseInt(num::ParseIntError);
     }
 }
+
+impl ErrorKind {
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ErrorKind::ConfigTrackNoSetup => "config_track_no_setup",
+            ErrorKind::BranchNotNamed => "branch_not_named",
+            ErrorKind::RemoteNotNamed => "remote_not_named",
+            ErrorKind::UnknownProfile(_) => "unknown_profile",
+            ErrorKind::InvalidProxy(_) => "invalid_proxy",
+            ErrorKind::InvalidCABundle(_) => "invalid_ca_bundle",
+            ErrorKind::FailedToParseRepository => "failed_to_parse_repository",
+            ErrorKind::ForkTimedOut(_) => "fork_timed_out",
+            ErrorKind::MalformedResponse(_) => "malformed_response",
+            ErrorKind::AuthenticationFailed(_) => "authentication_failed",
+            ErrorKind::InsufficientPermissions(_) => "insufficient_permissions",
+            ErrorKind::SshAuthenticationFailed(_) => "ssh_authentication_failed",
+            ErrorKind::HostKeyMismatch(..) => "host_key_mismatch",
+            ErrorKind::UnknownHostKey(..) => "unknown_host_key",
+            ErrorKind::MissingCredentials(_) => "missing_credentials",
+            ErrorKind::OTPRequired => "otp_required",
+            ErrorKind::DeviceCodeExpired => "device_code_expired",
+            ErrorKind::AuthorizationDenied => "authorization_denied",
+            ErrorKind::NoOAuthClientId(_) => "no_oauth_client_id",
+            ErrorKind::NotLoggedIn(_) => "not_logged_in",
+            ErrorKind::PasswordCommandFailed(..) => "password_command_failed",
+            ErrorKind::RateLimited(_) => "rate_limited",
+            ErrorKind::APIError(_) => "api_error",
+            ErrorKind::RawAPIError(_) => "raw_api_error",
+            ErrorKind::Interrupted => "interrupted",
+            ErrorKind::ClonePathExists(_) => "clone_path_exists",
+            ErrorKind::NothingToResume(_) => "nothing_to_resume",
+            ErrorKind::AppDirs(_) => "app_dirs",
+            ErrorKind::Io(_) => "io",
+            ErrorKind::Yaml(_) => "yaml",
+            ErrorKind::Git(_) => "git",
+            ErrorKind::Regex(_) => "regex",
+            ErrorKind::Reqwest(_) => "http",
+            ErrorKind::ParseInt(_) => "parse_int",
+            ErrorKind::Keyring(_) => "keyring",
+            _ => "other",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ErrorKind::ConfigTrackNoSetup | ErrorKind::FailedToParseRepository | ErrorKind::NothingToResume(_) => exit_code::OPTIONS,
+            ErrorKind::UnknownProfile(_) | ErrorKind::InvalidProxy(_) | ErrorKind::InvalidCABundle(_) => exit_code::OPTIONS,
+            ErrorKind::AppDirs(_) | ErrorKind::Yaml(_) | ErrorKind::ParseInt(_) => exit_code::OPTIONS,
+            ErrorKind::AuthenticationFailed(_) | ErrorKind::InsufficientPermissions(_) | ErrorKind::SshAuthenticationFailed(_) | ErrorKind::MissingCredentials(_) => exit_code::AUTHENTICATION,
+            ErrorKind::OTPRequired | ErrorKind::DeviceCodeExpired | ErrorKind::AuthorizationDenied => exit_code::AUTHENTICATION,
+            ErrorKind::HostKeyMismatch(..) | ErrorKind::UnknownHostKey(..) => exit_code::AUTHENTICATION,
+            ErrorKind::NotLoggedIn(_) | ErrorKind::PasswordCommandFailed(..) | ErrorKind::Keyring(_) => exit_code::AUTHENTICATION,
+            ErrorKind::NoOAuthClientId(_) => exit_code::OPTIONS,
+            ErrorKind::APIError(_) | ErrorKind::RawAPIError(_) | ErrorKind::MalformedResponse(_) => exit_code::API,
+            ErrorKind::RateLimited(_) => exit_code::API,
+            ErrorKind::Reqwest(ref err) if api::is_connection_error(err) => exit_code::NETWORK,
+            ErrorKind::Reqwest(_) => exit_code::API,
+            ErrorKind::ForkTimedOut(_) => exit_code::FORK_TIMED_OUT,
+            ErrorKind::ClonePathExists(_) => exit_code::FILESYSTEM_CONFLICT,
+            ErrorKind::Interrupted => interrupt::EXIT_CODE,
+            ErrorKind::Git(ref err) => git_exit_code(err.code(), err.class()),
+            _ => exit_code::OTHER,
+        }
+    }
+}
+
+pub fn git_exit_code(code: git2::ErrorCode, class: git2::ErrorClass) -> i32 {
+    match (code, class) {
+        (git2::ErrorCode::Auth, _) => exit_code::AUTHENTICATION,
+        (git2::ErrorCode::Exists, _) => exit_code::FILESYSTEM_CONFLICT,
+        (_, git2::ErrorClass::Net) | (_, git2::ErrorClass::Ssh) | (_, git2::ErrorClass::Ssl) => exit_code::NETWORK,
+        _ => exit_code::OTHER,
+    }
+}
+
+impl Error {
+    pub fn specific_kind(&self) -> &ErrorKind {
+        let mut err = self;
+        while let ErrorKind::Msg(_) = *err.kind() {
+            match err.1.next_error.as_ref().and_then(|next| next.downcast_ref::<Error>()) {
+                Some(next) => err = next,
+                None => break,
+            }
+        }
+        err.kind()
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use secrets::Secret;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitCredential {
+    pub protocol: String,
+    pub host: String,
+    pub username: Option<String>,
+    pub password: Secret,
+}
+
+pub fn parse_credential(output: &str) -> Option<GitCredential> {
+    let mut protocol = None;
+    let mut host = None;
+    let mut username = None;
+    let mut password = None;
+    for line in output.lines() {
+        let mut parts = line.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some("protocol"), Some(value)) => protocol = Some(value.to_string()),
+            (Some("host"), Some(value)) => host = Some(value.to_string()),
+            (Some("username"), Some(value)) => username = Some(value.to_string()),
+            (Some("password"), Some(value)) => password = Some(Secret::new(value.to_string())),
+            _ => {}
+        }
+    }
+    Some(GitCredential {
+        protocol: protocol.unwrap_or_else(|| "https".into()),
+        host: host?,
+        username: username,
+        password: password?,
+    })
+}
+
+fn git_credential(action: &str, input: &str) -> io::Result<Option<String>> {
+    let mut child = Command::new("git")
+        .args(&["credential", action])
+        // ghcl prompts for a token itself, so git shouldn't ask for a username and password
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    child.stdin.take().expect("stdin was piped").write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(output.stdout).ok())
+}
+
+impl GitCredential {
+    pub fn fill(host: &str) -> Option<GitCredential> {
+        let input = format!("protocol=https\nhost={}\n\n", host);
+        match git_credential("fill", &input) {
+            Ok(Some(output)) => parse_credential(&output),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut description = format!("protocol={}\nhost={}\n", self.protocol, self.host);
+        if let Some(ref username) = self.username {
+            description.push_str(&format!("username={}\n", username));
+        }
+        description.push_str(&format!("password={}\n\n", self.password.expose()));
+        description
+    }
+
+    pub fn approve(&self) -> io::Result<()> {
+        git_credential("approve", &self.describe()).map(|_| ())
+    }
+
+    pub fn reject(&self) -> io::Result<()> {
+        git_credential("reject", &self.describe()).map(|_| ())
+    }
+}
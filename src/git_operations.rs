@@ -1,18 +1,89 @@
 use io::{self, Write};
-use std::path::Path;
+use std::fs;
+use std::env;
+use std::vec;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{self, AtomicBool};
 
 use git2::{self, Repository, Remote, Branch, ResetType, FetchOptions, RemoteCallbacks};
-use git2::build::RepoBuilder;
+use git2::build::CheckoutBuilder;
 
-use options::Authentication;
+use options::{expand_home, Credentials};
+use interrupt;
+use prompt;
+use ssh_config::{self, SshUrl};
+use host_keys;
+use network::Network;
+use verbose;
 use errors::*;
 
-fn get_fetchoptions<'a>(quiet: bool, auth: &'a Authentication, progressed: &'a AtomicBool) -> FetchOptions<'a> {
+const DEFAULT_SSH_KEYS: &[&str] = &["~/.ssh/id_ed25519", "~/.ssh/id_rsa"];
+
+/// "openssh-key-v1" followed by the cipher "none", in base64
+const UNENCRYPTED_OPENSSH_KEY_PREFIX: &str = "b3BlbnNzaC1rZXktdjEAAAAABG5vbmU";
+
+enum SshAttempt {
+    Agent,
+    KeyFile(PathBuf),
+}
+
+/// The host's identity files come before the agent, which may have another account's key
+fn ssh_attempts(identity_files: &[PathBuf], ssh_key: Option<&str>) -> vec::IntoIter<SshAttempt> {
+    let mut attempts: Vec<SshAttempt> = identity_files.iter().filter(|path| path.is_file()).cloned().map(SshAttempt::KeyFile).collect();
+    if cfg!(not(unix)) || env::var_os("SSH_AUTH_SOCK").is_some() {
+        attempts.push(SshAttempt::Agent);
+    }
+    let key_files = ssh_key.into_iter().chain(DEFAULT_SSH_KEYS.iter().cloned()).map(expand_home);
+    for path in key_files {
+        if path.is_file() && !attempts.iter().any(|attempt| match *attempt { SshAttempt::KeyFile(ref p) => *p == path, _ => false }) {
+            attempts.push(SshAttempt::KeyFile(path));
+        }
+    }
+    attempts.into_iter()
+}
+
+pub fn ssh_key_encrypted(contents: &str) -> bool {
+    if contents.contains("BEGIN OPENSSH PRIVATE KEY") {
+        !contents.lines().nth(1).map_or(false, |line| line.starts_with(UNENCRYPTED_OPENSSH_KEY_PREFIX))
+    } else {
+        contents.contains("ENCRYPTED")
+    }
+}
+
+/// `None` if the key is unreadable, or encrypted and ghcl can't prompt
+fn ssh_key_file_cred(username: &str, path: &Path) -> Option<Result<git2::Cred, git2::Error>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let passphrase = if ssh_key_encrypted(&contents) {
+        if !prompt::is_interactive() {
+            return None;
+        }
+        match prompt::ask_for(&format!("Passphrase for {}: ", path.display()), true) {
+            Ok(passphrase) => Some(passphrase),
+            Err(_) if interrupt::is_interrupted() => return Some(Err(git2::Error::from_str("interrupted"))),
+            Err(_) => return None,
+        }
+    } else {
+        None
+    };
+    let public_key = path.with_file_name(format!("{}.pub", path.file_name()?.to_string_lossy()));
+    let public_key = if public_key.is_file() { Some(public_key.as_path()) } else { None };
+    Some(git2::Cred::ssh_key(username, public_key, path, passphrase.as_ref().map(|s| s.as_str())))
+}
+
+/// libgit2 calls the credentials callback again whenever its credentials are rejected, so each method is only tried once
+fn get_fetchoptions<'a>(url: &str, quiet: bool, credentials: &'a Credentials, network: &Network, mut ssh_attempts: vec::IntoIter<SshAttempt>, progressed: &'a AtomicBool, auth_error: &'a Cell<Option<Error>>) -> FetchOptions<'a> {
     let mut options = FetchOptions::new();
+    options.proxy_options(network.proxy_options(url));
     let mut callbacks = RemoteCallbacks::new();
+    let mut userpass_tried = false;
+    let mut default_tried = false;
     callbacks.credentials(move |url, username, allowed| {
         progressed.store(true, atomic::Ordering::Relaxed);
+        if interrupt::is_interrupted() {
+            return Err(git2::Error::from_str("interrupted"));
+        }
+        verbose!(verbose::TRACE, "{} asked for credentials ({:?}, username {:?})", url, allowed, username);
         let config = git2::Config::open_default()?;
         let mut cred_helper = git2::CredentialHelper::new(url);
         cred_helper.config(&config);
@@ -20,65 +91,180 @@ fn get_fetchoptions<'a>(quiet: bool, auth: &'a Authentication, progressed: &'a A
             let user = username.map(|s| s.to_string())
                                .or_else(|| cred_helper.username.clone())
                                .unwrap_or("git".to_string());
-            git2::Cred::ssh_key_from_agent(&user)
+            loop {
+                match ssh_attempts.next() {
+                    Some(SshAttempt::Agent) => {
+                        verbose!(verbose::DEBUG, "Trying the SSH agent as {}", user);
+                        return git2::Cred::ssh_key_from_agent(&user);
+                    }
+                    Some(SshAttempt::KeyFile(path)) => {
+                        verbose!(verbose::DEBUG, "Trying the SSH key {} as {}", path.display(), user);
+                        if let Some(cred) = ssh_key_file_cred(&user, &path) {
+                            return cred;
+                        }
+                        verbose!(verbose::TRACE, "Skipping {}, as it can't be read or needs a passphrase", path.display());
+                    }
+                    None => {
+                        auth_error.set(Some(ErrorKind::SshAuthenticationFailed(url.to_string()).into()));
+                        return Err(git2::Error::from_str("SSH authentication failed"));
+                    }
+                }
+            }
         } else if allowed.contains(git2::USER_PASS_PLAINTEXT) {
-            git2::Cred::userpass_plaintext(&auth.username, &auth.password)
+            if userpass_tried {
+                auth_error.set(Some(ErrorKind::AuthenticationFailed(format!("{} rejected the credentials", url)).into()));
+                return Err(git2::Error::from_str("authentication failed"));
+            }
+            userpass_tried = true;
+            let auth = match credentials.get() {
+                Ok(auth) => auth,
+                Err(err) => {
+                    let message = err.to_string();
+                    auth_error.set(Some(err));
+                    return Err(git2::Error::from_str(&message));
+                }
+            };
+            let (username, password) = auth.git_credentials();
+            verbose!(verbose::DEBUG, "Trying HTTPS authentication as {}", username);
+            git2::Cred::userpass_plaintext(username, password)
         } else if allowed.contains(git2::DEFAULT) {
+            if default_tried {
+                auth_error.set(Some(ErrorKind::AuthenticationFailed(format!("{} rejected the default credentials", url)).into()));
+                return Err(git2::Error::from_str("authentication failed"));
+            }
+            default_tried = true;
+            verbose!(verbose::DEBUG, "Trying the default credentials");
             git2::Cred::default()
         } else {
             Err(git2::Error::from_str("no authentication available"))
         }
     });
-    if !quiet {
-        let mut stage = 0;
-        callbacks.transfer_progress(move |progress| {
-            progressed.store(true, atomic::Ordering::Relaxed);
-            let mut stderr = io::stderr();
-            let objects = (progress.received_objects(), progress.total_objects());
-            let deltas = (progress.indexed_deltas(), progress.total_deltas());
-            if objects.0 != objects.1 {
-                if stage == 0 {
-                    stage = 1;
-                } else {
-                    write!(stderr, "\r").ok();
+    // Only SSH host keys are checked, as returning true for an HTTPS certificate would accept it even if it's invalid
+    if SshUrl::parse(url).is_some() {
+        callbacks.certificate_check(move |cert, hostname| {
+            let verified = match cert.as_hostkey().and_then(|hostkey| hostkey.hash_sha1()) {
+                Some(sha1) => host_keys::verify(hostname, sha1),
+                None => Err(ErrorKind::UnknownHostKey(hostname.into(), "unknown".into())),
+            };
+            verbose!(verbose::TRACE, "Host key for {}: {}", hostname, if verified.is_ok() { "verified" } else { "rejected" });
+            match verified {
+                Ok(()) => true,
+                Err(kind) => {
+                    auth_error.set(Some(kind.into()));
+                    false
                 }
-                let percent = if objects.1 == 0 { 0 } else { 100*objects.0/objects.1 };
-                write!(stderr, "Receiving objects: {}% ({}/{})", percent, objects.0, objects.1).ok();
-                return true;
             }
-            if stage == 1 {
-                writeln!(stderr, "").ok();
-                stage = 2;
+        });
+    }
+    let mut stage = 0;
+    callbacks.transfer_progress(move |progress| {
+        progressed.store(true, atomic::Ordering::Relaxed);
+        if quiet {
+            return !interrupt::is_interrupted();
+        }
+        let mut stderr = io::stderr();
+        let objects = (progress.received_objects(), progress.total_objects());
+        let deltas = (progress.indexed_deltas(), progress.total_deltas());
+        if objects.0 != objects.1 {
+            if stage == 0 {
+                stage = 1;
             } else {
                 write!(stderr, "\r").ok();
             }
-            let percent = if deltas.1 == 0 { 0 } else { 100*deltas.0/deltas.1 };
-            write!(stderr, "Receiving deltas: {}% ({}/{})", percent, deltas.0, deltas.1).ok();
-            true
-        });
-        callbacks.sideband_progress(move |message| {
-            progressed.store(true, atomic::Ordering::Relaxed);
+            let percent = if objects.1 == 0 { 0 } else { 100*objects.0/objects.1 };
+            write!(stderr, "Receiving objects: {}% ({}/{})", percent, objects.0, objects.1).ok();
+            return !interrupt::is_interrupted();
+        }
+        if stage == 1 {
+            writeln!(stderr, "").ok();
+            stage = 2;
+        } else {
+            write!(stderr, "\r").ok();
+        }
+        let percent = if deltas.1 == 0 { 0 } else { 100*deltas.0/deltas.1 };
+        write!(stderr, "Receiving deltas: {}% ({}/{})", percent, deltas.0, deltas.1).ok();
+        !interrupt::is_interrupted()
+    });
+    callbacks.sideband_progress(move |message| {
+        progressed.store(true, atomic::Ordering::Relaxed);
+        if !quiet {
             let mut stderr = io::stderr();
             write!(stderr, "\rremote: ").ok();
             stderr.write_all(message).ok();
-            true
-        });
-    }
+        }
+        !interrupt::is_interrupted()
+    });
     options.remote_callbacks(callbacks);
     options
 }
 
-pub fn clone_repo<P: AsRef<Path>>(url: &str, location: P, auth: &Authentication, quiet: bool, progressed: &mut bool) -> Result<Repository> {
+pub fn init_repo<P: AsRef<Path>>(url: &str, location: P) -> Result<Repository> {
+    let location = location.as_ref();
+    if location.is_file() || (location.is_dir() && location.read_dir()?.next().is_some()) {
+        Err(ErrorKind::ClonePathExists(location.display().to_string()))?
+    }
+    verbose!(verbose::DEBUG, "Creating a repository in {} with origin {}", location.display(), url);
+    let repo = Repository::init(location)?;
+    repo.remote("origin", url)?;
+    Ok(repo)
+}
+
+/// Unlike a normal clone, a failure here leaves the repository behind, so it can be resumed
+pub fn clone_repo(repo: &Repository, credentials: &Credentials, network: &Network, ssh_key: Option<&str>, quiet: bool, progressed: &mut bool) -> Result<()> {
+    let origin = repo.find_remote("origin")?;
+    let url = origin.url().ok_or("origin URL is not valid UTF-8")?.to_string();
+    let (connect_url, identity_files) = ssh_config::resolve(&url);
+    if connect_url != url {
+        verbose!(verbose::DEBUG, "{} resolves to {} with ~/.ssh/config", url, connect_url);
+    }
+    verbose!(verbose::DEBUG, "Fetching {}", connect_url);
+    let (mut remote, refspecs): (Remote, &[&str]) = if connect_url == url {
+        (origin, &[])
+    } else {
+        (repo.remote_anonymous(&connect_url)?, &["+refs/heads/*:refs/remotes/origin/*"])
+    };
     let mut progressed_atomic = AtomicBool::new(*progressed);
-    let repo = RepoBuilder::new().fetch_options(get_fetchoptions(quiet, auth, &mut progressed_atomic)).clone(url, location.as_ref())?;
+    let auth_error = Cell::new(None);
+    let res = remote.fetch(refspecs, Some(&mut get_fetchoptions(&connect_url, quiet, credentials, network, ssh_attempts(&identity_files, ssh_key), &mut progressed_atomic, &auth_error)), None);
     *progressed = progressed_atomic.into_inner();
+    interrupt::check()?;
+    if let Some(err) = auth_error.take() {
+        return Err(err);
+    }
+    res?;
     if !quiet {
         writeln!(io::stderr(), "").ok();
     }
-    Ok(repo)
+    checkout_remote_head(repo, &remote)
+}
+
+fn checkout_remote_head(repo: &Repository, remote: &Remote) -> Result<()> {
+    let (branch_ref, oid) = {
+        let heads = remote.list()?;
+        let head = match heads.iter().find(|head| head.name() == "HEAD") {
+            Some(head) => head,
+            None => return Ok(()), // empty repository
+        };
+        let branch_ref = head.symref_target().map(String::from).or_else(|| {
+            heads.iter().find(|branch| branch.name().starts_with("refs/heads/") && branch.oid() == head.oid()).map(|branch| branch.name().to_string())
+        }).ok_or(ErrorKind::BranchNotNamed)?;
+        (branch_ref, head.oid())
+    };
+    let branch_name = if branch_ref.starts_with("refs/heads/") {
+        &branch_ref["refs/heads/".len()..]
+    } else {
+        Err(ErrorKind::BranchNotNamed)?
+    };
+    verbose!(verbose::DEBUG, "Checking out {} at {}", branch_name, oid);
+    let commit = repo.find_commit(oid)?;
+    let mut branch = repo.branch(branch_name, &commit, true)?;
+    branch.set_upstream(Some(&format!("origin/{}", branch_name)))?;
+    repo.set_head(&branch_ref)?;
+    Ok(repo.checkout_head(Some(CheckoutBuilder::new().force()))?)
 }
 
 pub fn setup_upstream<'a>(repository: &'a Repository, name: &str, orig_url: &str) -> Result<Remote<'a>> {
+    verbose!(verbose::DEBUG, "Adding the remote {} for {}", name, orig_url);
     Ok(repository.remote(name, orig_url)?)
 }
 
@@ -86,9 +272,17 @@ pub fn get_head_branch<'a>(repository: &'a Repository) -> Result<Branch<'a>> {
     Ok(Branch::wrap(repository.head()?))
 }
 
-pub fn fetch_remote(remote: &mut Remote, branch: &Branch, auth: &Authentication, quiet: bool) -> Result<()> {
+pub fn fetch_remote(remote: &mut Remote, branch: &Branch, credentials: &Credentials, network: &Network, ssh_key: Option<&str>, quiet: bool) -> Result<()> {
     let mut progressed = AtomicBool::new(false);
-    remote.fetch(&[branch.name()?.ok_or(ErrorKind::BranchNotNamed)?], Some(&mut get_fetchoptions(quiet, auth, &mut progressed)), None)?;
+    let auth_error = Cell::new(None);
+    let url = remote.url().ok_or("remote URL is not valid UTF-8")?.to_string();
+    verbose!(verbose::DEBUG, "Fetching {} from {}", branch.name()?.unwrap_or("(unnamed)"), url);
+    let res = remote.fetch(&[branch.name()?.ok_or(ErrorKind::BranchNotNamed)?], Some(&mut get_fetchoptions(&url, quiet, credentials, network, ssh_attempts(&[], ssh_key), &mut progressed, &auth_error)), None);
+    interrupt::check()?;
+    if let Some(err) = auth_error.take() {
+        return Err(err);
+    }
+    res?;
     if !quiet {
         writeln!(io::stderr(), "").ok();
     }
@@ -97,10 +291,12 @@ pub fn fetch_remote(remote: &mut Remote, branch: &Branch, auth: &Authentication,
 
 pub fn track_upstream(branch: &mut Branch, remote: &Remote) -> Result<()> {
     let upstream_name = remote.name().ok_or(ErrorKind::RemoteNotNamed)?.to_string() + "/" + branch.name()?.ok_or(ErrorKind::BranchNotNamed)?;
+    verbose!(verbose::DEBUG, "Setting the upstream of {} to {}", branch.name()?.unwrap_or("(unnamed)"), upstream_name);
     Ok(branch.set_upstream(Some(&upstream_name))?)
 }
 
 pub fn hard_reset_fetch_head(repo: &Repository) -> Result<()> {
     let fetch_head = repo.revparse_single("FETCH_HEAD")?;
+    verbose!(verbose::DEBUG, "Resetting to FETCH_HEAD at {}", fetch_head.id());
     Ok(repo.reset(&fetch_head, ResetType::Hard, None)?)
 }
@@ -0,0 +1,88 @@
+use std::fmt;
+
+use time;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Permissions {
+    #[serde(default)]
+    pub push: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Repo {
+    pub full_name: String,
+    pub ssh_url: String,
+    pub clone_url: String,
+    pub default_branch: String,
+    pub created_at: Option<String>,
+    /// Only included when getting a single repository
+    pub parent: Option<Box<Repo>>,
+    /// Only included when authenticated
+    pub permissions: Option<Permissions>,
+}
+
+impl Repo {
+    pub fn created_at_unix(&self) -> Option<u64> {
+        let created_at = self.created_at.as_ref()?;
+        let created = time::strptime(created_at, "%Y-%m-%dT%H:%M:%SZ").ok()?.to_timespec();
+        if created.sec < 0 { None } else { Some(created.sec as u64) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForkRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ErrorDetail {
+    Field {
+        resource: Option<String>,
+        field: Option<String>,
+        code: Option<String>,
+        message: Option<String>,
+    },
+    Message(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiError {
+    pub message: String,
+    #[serde(default)]
+    pub errors: Vec<ErrorDetail>,
+    pub documentation_url: Option<String>,
+}
+
+impl fmt::Display for ErrorDetail {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorDetail::Field { message: Some(ref message), .. } => write!(f, "{}", message),
+            ErrorDetail::Field { ref resource, ref field, ref code, .. } => {
+                let parts: Vec<&str> = [resource, field, code].iter().filter_map(|part| part.as_ref().map(|s| s.as_str())).collect();
+                write!(f, "{}", parts.join(" "))
+            }
+            ErrorDetail::Message(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.errors.is_empty() {
+            let details: Vec<String> = self.errors.iter().map(ToString::to_string).collect();
+            write!(f, " ({})", details.join("; "))?;
+        }
+        if let Some(ref url) = self.documentation_url {
+            write!(f, " (see {})", url)?;
+        }
+        Ok(())
+    }
+}
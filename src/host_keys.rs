@@ -0,0 +1,156 @@
+use std::io::{self, Write};
+use std::fs::{self, OpenOptions};
+use std::path::PathBuf;
+
+use base64;
+use sha1::Sha1;
+
+use options::{config_dir, expand_home};
+use ssh_config::host_matches;
+use prompt;
+use errors::ErrorKind;
+
+/// SHA-1 hashes of the host keys the forges publish, as libgit2 reports them
+const PINNED_HOST_KEYS: &[(&str, &[&str])] = &[
+    ("github.com", &[
+        "e9619e2ed56c2f2a71729db80bacc2ce9ccce8d4", // ed25519, SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU
+        "3358ab5dd3e306c461c840f7487e93b697e30600", // ecdsa, SHA256:p2QAMXNIC1TJYWeIOttrVc98/R1BUFWu3/LiyKgUfQM
+        "6f4c60375018bae0918e37d9162bc15ba40e6365", // rsa, SHA256:uNiVztksCsDhcc0u9e8BujQXVUpKZIDTMczCvj3tD2s
+    ]),
+    ("gitlab.com", &[
+        "51f9a9fbcffc06f4cb194414eea6ccfad2f6202b", // ed25519, SHA256:eUXGGm1YGsMAS7vkcx6JOJdOGHPem5gQp4taiCfCLB8
+        "4db6b9ab0209fcde106cbf0fc4560ad063a962ad", // ecdsa, SHA256:HbW3g8zUjNSksFbqTiUWPWg2Bq1x8xdGUrliXFzSnUw
+        "b5fa4b9508430c53f7c8675ec13bc73715a601d9", // rsa, SHA256:ROQFvPThGrW4RuWLoL9tq9I9zJ42fK4XywyRtbOz/EQ
+    ]),
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Known {
+    Trusted,
+    Revoked,
+    Mismatch,
+    Unknown,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn fingerprint(sha1: &[u8]) -> String {
+    format!("SHA1:{}", base64::encode(sha1).trim_end_matches('='))
+}
+
+pub fn hmac_sha1(key: &[u8], message: &[u8]) -> [u8; 20] {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..20].copy_from_slice(&Sha1::from(key).digest().bytes());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha1::new();
+    inner.update(&block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.update(message);
+    let mut outer = Sha1::new();
+    outer.update(&block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(&inner.digest().bytes());
+    outer.digest().bytes()
+}
+
+fn known_host_matches(hosts: &str, hostname: &str) -> bool {
+    if hosts.starts_with("|1|") {
+        let mut parts = hosts["|1|".len()..].splitn(2, '|');
+        match (parts.next().and_then(|salt| base64::decode(salt).ok()), parts.next().and_then(|hash| base64::decode(hash).ok())) {
+            (Some(salt), Some(hash)) => hmac_sha1(&salt, hostname.as_bytes())[..] == hash[..],
+            _ => false,
+        }
+    } else {
+        host_matches(&hosts.replace(',', " "), hostname)
+    }
+}
+
+/// Lines are either OpenSSH's `[marker] hosts keytype key`, or `hostname SHA1:fingerprint` as ghcl saves them
+pub fn check_known_hosts(contents: &str, hostname: &str, sha1: &[u8]) -> Known {
+    let mut known = Known::Unknown;
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace().peekable();
+        let marker = match fields.peek() {
+            Some(field) if field.starts_with('@') => fields.next(),
+            _ => None,
+        };
+        let hosts = match fields.next() {
+            Some(hosts) => hosts,
+            None => continue,
+        };
+        if marker == Some("@cert-authority") || !known_host_matches(hosts, hostname) {
+            continue;
+        }
+        let matches = match (fields.next(), fields.next()) {
+            (Some(fingerprint_field), None) => fingerprint_field == fingerprint(sha1),
+            (Some(_), Some(key)) => base64::decode(key).map(|key| Sha1::from(key).digest().bytes()[..] == sha1[..]).unwrap_or(false),
+            _ => continue,
+        };
+        match (marker, matches) {
+            (Some("@revoked"), true) => return Known::Revoked,
+            (Some("@revoked"), false) => {}
+            (_, true) => known = Known::Trusted,
+            (_, false) => if known == Known::Unknown {
+                known = Known::Mismatch;
+            },
+        }
+    }
+    known
+}
+
+/// libgit2 doesn't give the whole key, so it can't be added to `~/.ssh/known_hosts`
+fn trusted_hosts_path() -> Option<PathBuf> {
+    config_dir().ok().map(|dir| dir.join("known_hosts"))
+}
+
+fn trust(hostname: &str, sha1: &[u8]) -> io::Result<()> {
+    let path = trusted_hosts_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    writeln!(file, "{} {}", hostname, fingerprint(sha1))
+}
+
+pub fn verify(hostname: &str, sha1: &[u8]) -> ::std::result::Result<(), ErrorKind> {
+    if let Some(&(_, keys)) = PINNED_HOST_KEYS.iter().find(|&&(host, _)| host.eq_ignore_ascii_case(hostname)) {
+        if keys.contains(&hex(sha1).as_str()) {
+            return Ok(());
+        }
+        return Err(ErrorKind::HostKeyMismatch(hostname.into(), fingerprint(sha1)));
+    }
+    let files = vec![Some(expand_home("~/.ssh/known_hosts")), trusted_hosts_path()];
+    let mut known = Known::Unknown;
+    for contents in files.into_iter().flatten().filter_map(|path| fs::read_to_string(path).ok()) {
+        match check_known_hosts(&contents, hostname, sha1) {
+            Known::Trusted => return Ok(()),
+            Known::Revoked => return Err(ErrorKind::HostKeyMismatch(hostname.into(), fingerprint(sha1))),
+            Known::Mismatch => known = Known::Mismatch,
+            Known::Unknown => {}
+        }
+    }
+    if known == Known::Mismatch {
+        return Err(ErrorKind::HostKeyMismatch(hostname.into(), fingerprint(sha1)));
+    }
+    if !prompt::is_interactive() {
+        return Err(ErrorKind::UnknownHostKey(hostname.into(), fingerprint(sha1)));
+    }
+    let mut stderr = io::stderr();
+    writeln!(stderr, "\nThe authenticity of host '{}' can't be established.", hostname).ok();
+    writeln!(stderr, "Its key fingerprint is {}.", fingerprint(sha1)).ok();
+    let answer = prompt::ask_for("Are you sure you want to continue connecting (yes/no)? ", false).map_err(|err| err.0)?;
+    if answer.trim() != "yes" {
+        return Err(ErrorKind::UnknownHostKey(hostname.into(), fingerprint(sha1)));
+    }
+    if let Err(err) = trust(hostname, sha1) {
+        writeln!(stderr, "Warning: failed to save the host key, so you'll be asked again: {}", err).ok();
+    }
+    Ok(())
+}
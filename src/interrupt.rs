@@ -0,0 +1,53 @@
+use std::cmp;
+use std::thread;
+use std::process;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ctrlc;
+
+use prompt;
+use errors::*;
+
+/// 128 + SIGINT, like a shell
+pub const EXIT_CODE: i32 = 130;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// A second Ctrl-C exits immediately, unless exiting would leave terminal echo off
+pub fn install_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        let interrupted = INTERRUPTED.swap(true, Ordering::SeqCst);
+        if interrupted && !prompt::is_prompting_securely() {
+            process::exit(EXIT_CODE);
+        }
+        if prompt::is_prompting() {
+            prompt::notify_interrupted();
+        }
+    }).chain_err(|| "failed to install Ctrl-C handler")
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+pub fn check() -> Result<()> {
+    if is_interrupted() {
+        Err(ErrorKind::Interrupted)?
+    }
+    Ok(())
+}
+
+pub fn sleep(duration: Duration) -> Result<()> {
+    let start = Instant::now();
+    loop {
+        check()?;
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            return Ok(());
+        }
+        thread::sleep(cmp::min(duration - elapsed, POLL_INTERVAL));
+    }
+}
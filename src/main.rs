@@ -1,10 +1,11 @@
 #![recursion_limit="128"] // for error-chain
 use std::io;
-use std::thread;
+use std::fs;
 use std::process;
 use std::io::prelude::*;
 use std::borrow::Borrow;
-use std::time::Duration;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 extern crate clap;
 extern crate git2;
@@ -14,25 +15,64 @@ extern crate serde_yaml;
 extern crate app_dirs2;
 extern crate regex;
 extern crate reqwest;
+extern crate hyper;
+extern crate time;
 extern crate rpassword;
 extern crate serde;
 extern crate serde_json;
+extern crate ctrlc;
+#[cfg(feature = "keyring")]
+extern crate keyring;
+extern crate atty;
+extern crate base64;
+extern crate sha1;
+
+#[macro_use] mod verbose;
 
 mod repository;
 
+mod api;
+use api::Api;
+
+mod github;
+
 mod errors;
 use errors::*;
 
 mod options;
-use options::{get_options, Options};
+use options::{get_matches, get_output_format, get_options, Command, Options};
 
 mod git_operations;
 use git_operations::*;
 
+mod interrupt;
+
+mod state;
+use state::State;
+
+mod output;
+use output::{OutputFormat, Report, Plan, PlannedFork, PlannedClone, Remote, TrackedBranch};
+
+mod credentials;
+
+mod secrets;
+
+mod git_credential;
+
+mod prompt;
+
+mod ssh_config;
+
+mod host_keys;
+
+mod network;
+
+mod auth;
+
 #[cfg(test)]
 mod tests;
 
-fn handle_retry<F: FnMut(&Options, &mut bool) -> Result<git2::Repository>>(options: &Options, mut f: F) -> Result<git2::Repository> {
+fn handle_retry<T, F: FnMut(&Options, &mut bool) -> Result<T>>(options: &Options, mut f: F) -> Result<T> {
     let mut stderr = io::stderr();
     let mut total_wait = 0;
     let mut timeout = Duration::from_secs(2);
@@ -51,7 +91,7 @@ fn handle_retry<F: FnMut(&Options, &mut bool) -> Result<git2::Repository>>(optio
             if !options.quiet {
                 writeln!(stderr, "Fork not yet created, waiting {} seconds", timeout.as_secs()).ok();
             }
-            thread::sleep(timeout);
+            interrupt::sleep(timeout)?;
             total_wait += timeout.as_secs();
             timeout *= 2;
             continue;
@@ -61,47 +101,237 @@ fn handle_retry<F: FnMut(&Options, &mut bool) -> Result<git2::Repository>>(optio
     }
 }
 
-fn main_inner() -> Result<()> {
-    let mut stderr = io::stderr();
-    let options = get_options().chain_err(|| "Failed to get options")?;
-    if !options.quiet {
-        writeln!(stderr, "Forking repository...").ok();
+fn report_git_credential<T>(options: &Options, res: &Result<T>) {
+    if let Some(credential) = options.credentials.git_credential() {
+        match *res {
+            Ok(_) => {
+                credential.approve().ok();
+            }
+            Err(ref err) => if let ErrorKind::AuthenticationFailed(_) = *err.specific_kind() {
+                credential.reject().ok();
+            },
+        }
     }
-    let fork_git_url = options.repository.fork(options.authentication.clone(), options.organization.as_ref().map(Borrow::borrow), options.origin_protocol.clone())
-        .chain_err(|| "Failed to fork repository")?;
-    if !options.quiet {
-        writeln!(stderr, "Cloning repository...").ok();
+}
+
+fn origin_url(options: &Options, fork_git_url: String) -> String {
+    match options.ssh_host {
+        Some(ref host) => ssh_config::with_host(&fork_git_url, host),
+        None => fork_git_url,
+    }
+}
+
+/// A directory the user created to clone into is emptied rather than removed
+fn remove_partial_clone(path: &Path, created_dir: bool) -> Result<()> {
+    if created_dir {
+        return Ok(fs::remove_dir_all(path)?);
+    }
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        // The file type doesn't follow symlinks
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
     }
-    let repo = handle_retry(&options, |options, progressed| clone_repo(&fork_git_url, &options.clone_path, &options.authentication, options.quiet, progressed)).chain_err(|| "Failed to clone repository")?;
+    Ok(())
+}
+
+fn plan(state: &State, api: &Api) -> Result<Plan> {
+    let options = &state.options;
+    let repository = &options.repository;
+    let mut plan = Plan::default();
+    plan.profile = options.profile.clone();
+    let fork_git_url = match state.fork_git_url {
+        Some(ref url) => url.clone(),
+        None => {
+            let fork = repository.plan_fork(api, options.organization.as_ref().map(Borrow::borrow), options.origin_protocol.clone())
+                .chain_err(|| "Failed to look up fork")?;
+            plan.fork = Some(PlannedFork {
+                owner: fork.owner.clone(),
+                existed: fork.existing.is_some(),
+                can_push: fork.existing.as_ref().and_then(|existing| existing.permissions.as_ref()).map(|permissions| permissions.push),
+            });
+            origin_url(options, fork.git_url)
+        }
+    };
+    if !state.cloned {
+        plan.clone = Some(PlannedClone { url: fork_git_url, path: options.clone_path.clone() });
+    }
+    if options.setup_upstream && !state.upstream_added {
+        let upstream_git_url = repository.get_git_url(api, options.upstream_protocol.clone()).chain_err(|| "Failed to get upstream git URL")?;
+        plan.upstream = Some(Remote { name: options.remote_name.clone(), url: Some(upstream_git_url) });
+    }
+    if options.setup_upstream && options.track_upstream && !state.upstream_tracked {
+        let branch = repository.get_default_branch(api).chain_err(|| "Failed to get upstream default branch")?;
+        plan.tracked_branch = Some(TrackedBranch { upstream: format!("{}/{}", options.remote_name, branch), branch: branch });
+    }
+    Ok(plan)
+}
+
+fn print_plan(state: &State, api: &Api, output_format: OutputFormat) -> Result<()> {
+    let plan = plan(state, api)?;
+    if output_format == OutputFormat::Json {
+        return plan.print();
+    }
+    let mut stdout = io::stdout();
+    let options = &state.options;
+    let repository = &options.repository;
+    if let Some(ref profile) = plan.profile {
+        writeln!(stdout, "Would use the {} profile", profile)?;
+    }
+    match plan.fork {
+        None => writeln!(stdout, "Already forked")?,
+        Some(ref fork) if fork.existed => {
+            writeln!(stdout, "Would use the existing fork of {}/{} in {}", repository.user, repository.name, fork.owner)?;
+            if fork.can_push == Some(false) {
+                writeln!(stdout, "Warning: you can't push to {}/{}", fork.owner, repository.name)?;
+            }
+        }
+        Some(ref fork) => writeln!(stdout, "Would fork {}/{} into {}", repository.user, repository.name, fork.owner)?,
+    }
+    match plan.clone {
+        None => writeln!(stdout, "Already cloned into {}", options.clone_path)?,
+        Some(ref clone) => writeln!(stdout, "Would clone {} into {}", clone.url, clone.path)?,
+    }
+    if options.setup_upstream {
+        match plan.upstream {
+            None => writeln!(stdout, "Already added the {} remote", options.remote_name)?,
+            Some(ref remote) => writeln!(stdout, "Would add the {} remote with {}", remote.name, remote.url.as_ref().map_or("", |url| url.as_str()))?,
+        }
+    }
+    if let Some(ref tracked) = plan.tracked_branch {
+        writeln!(stdout, "Would set {} to track {} and hard reset it to match", tracked.branch, tracked.upstream)?;
+    }
+    Ok(())
+}
+
+fn run_steps(state: &mut State, api: &Api, repo: &git2::Repository, report: &mut Report) -> Result<()> {
+    let mut stderr = io::stderr();
+    let options = &state.options;
+    if !state.cloned {
+        if !options.quiet {
+            writeln!(stderr, "Cloning repository...").ok();
+        }
+        let start = Instant::now();
+        handle_retry(options, |options, progressed| clone_repo(repo, &options.credentials, &options.network, options.ssh_key.as_ref().map(|s| s.as_str()), options.quiet, progressed)).chain_err(|| "Failed to clone repository")?;
+        report.timings.clone = Some(output::seconds(start.elapsed()));
+        state.cloned = true;
+        state.save(repo)?;
+    }
+    interrupt::check()?;
     if options.setup_upstream {
-        let mut remote = setup_upstream(&repo, &options.remote_name, &options.repository.get_git_url(options.upstream_protocol).chain_err(|| "Failed to get upstream git URL")?)
-            .chain_err(|| "Failed to setup upstream")?;
-        if options.track_upstream {
+        let start = Instant::now();
+        let mut remote = if state.upstream_added {
+            repo.find_remote(&options.remote_name)?
+        } else {
+            let remote = setup_upstream(repo, &options.remote_name, &options.repository.get_git_url(api, options.upstream_protocol.clone()).chain_err(|| "Failed to get upstream git URL")?)
+                .chain_err(|| "Failed to setup upstream")?;
+            state.upstream_added = true;
+            state.save(repo)?;
+            remote
+        };
+        if options.track_upstream && !state.upstream_tracked {
             if !options.quiet {
                 writeln!(stderr, "Fetching and tracking upstream...").ok();
             }
-            let mut master = get_head_branch(&repo)?;
-            fetch_remote(&mut remote, &master, &options.authentication, true).chain_err(|| "Failed to fetch upstream")?;
+            let mut master = get_head_branch(repo)?;
+            fetch_remote(&mut remote, &master, &options.credentials, &options.network, options.ssh_key.as_ref().map(|s| s.as_str()), true).chain_err(|| "Failed to fetch upstream")?;
             track_upstream(&mut master, &remote).chain_err(|| "Failed to set master to track upstream")?;
-            hard_reset_fetch_head(&repo).chain_err(|| "Failed to hard reset to upstream")?;
+            hard_reset_fetch_head(repo).chain_err(|| "Failed to hard reset to upstream")?;
+            state.upstream_tracked = true;
+        }
+        report.timings.upstream = Some(output::seconds(start.elapsed()));
+    }
+    state.finish(repo)
+}
+
+fn main_inner(matches: &clap::ArgMatches, output_format: OutputFormat, resume_path: &mut Option<String>) -> Result<()> {
+    let mut stderr = io::stderr();
+    let start = Instant::now();
+    prompt::set_interactive(!matches.is_present("non_interactive"));
+    verbose::set_verbosity(matches.occurrences_of("verbose"));
+    let (mut state, created) = match get_options(matches).chain_err(|| "Failed to get options")? {
+        Command::Clone(options) => (State::new(options), true),
+        Command::Resume(state) => (state, false),
+        Command::Auth(auth_options) => return auth::run(&auth_options, output_format),
+    };
+    let api = Api::new(state.options.repository.service, &state.options.network, state.options.credentials.clone(), state.options.api_retries, state.options.quiet)?;
+    if state.options.dry_run {
+        return print_plan(&state, &api, output_format);
+    }
+    interrupt::install_handler()?;
+    let mut report = Report::default();
+    let mut created_dir = false;
+    let repo = if created {
+        let options = &state.options;
+        if !options.quiet {
+            if let Some(ref profile) = options.profile {
+                writeln!(stderr, "Using the {} profile", profile).ok();
+            }
+            writeln!(stderr, "Forking repository...").ok();
+        }
+        let fork_start = Instant::now();
+        let fork = options.repository.fork(&api, options.organization.as_ref().map(Borrow::borrow), options.origin_protocol.clone());
+        report_git_credential(options, &fork);
+        let fork = fork.chain_err(|| "Failed to fork repository")?;
+        report.fork_existed = fork.existed;
+        let fork_git_url = origin_url(options, fork.git_url);
+        report.timings.fork = Some(output::seconds(fork_start.elapsed()));
+        interrupt::check()?;
+        created_dir = !Path::new(&options.clone_path).exists();
+        let repo = init_repo(&fork_git_url, &options.clone_path).chain_err(|| "Failed to create repository")?;
+        state.fork_git_url = Some(fork_git_url);
+        state.save(&repo)?;
+        repo
+    } else {
+        git2::Repository::open(&state.options.clone_path)?
+    };
+    if let Err(err) = run_steps(&mut state, &api, &repo, &mut report) {
+        if interrupt::is_interrupted() && created && !state.cloned {
+            remove_partial_clone(Path::new(&state.options.clone_path), created_dir).chain_err(|| "Failed to remove partial clone")?;
+        } else {
+            *resume_path = Some(state.options.clone_path.clone());
         }
+        return Err(err);
     }
-    if !options.quiet {
+    if !state.options.quiet {
         writeln!(stderr, "Done!").ok();
     }
+    if output_format == OutputFormat::Json {
+        report.fork_url = state.fork_git_url.clone();
+        report.clone_path = state.options.clone_path.clone();
+        report.describe_repo(&repo)?;
+        report.timings.total = output::seconds(start.elapsed());
+        report.print()?;
+    }
     Ok(())
 }
 
 fn main() {
-    if let Err(e) = main_inner() {
+    let matches = get_matches();
+    let output_format = get_output_format(&matches);
+    let mut resume_path = None;
+    if let Err(e) = main_inner(&matches, output_format, &mut resume_path) {
         let mut stderr = io::stderr();
-        writeln!(stderr, "Error:     {}", e).expect("Failed to write to stderr");
+        // Errors can quote URLs and API responses
+        writeln!(stderr, "Error:     {}", secrets::scrub(&e.to_string())).expect("Failed to write to stderr");
         for e in e.iter().skip(1) {
-            writeln!(stderr, "caused by: {}", e).expect("Failed to write to stderr");
+            writeln!(stderr, "caused by: {}", secrets::scrub(&e.to_string())).expect("Failed to write to stderr");
         }
         if let Some(backtrace) = e.backtrace() {
-            writeln!(stderr, "backtrace: {:?}", backtrace).expect("Failed to write to stderr");
+            writeln!(stderr, "backtrace: {}", secrets::scrub(&format!("{:?}", backtrace))).expect("Failed to write to stderr");
+        }
+        if let Some(ref path) = resume_path {
+            writeln!(stderr, "Run `ghcl resume {}` to pick up where this left off", path).expect("Failed to write to stderr");
+        }
+        if output_format == OutputFormat::Json {
+            output::print_error(&e, resume_path.as_ref().map(Borrow::borrow)).expect("Failed to write to stdout");
+        }
+        if interrupt::is_interrupted() {
+            process::exit(interrupt::EXIT_CODE);
         }
-        process::exit(2);
+        process::exit(e.specific_kind().exit_code());
     }
 }
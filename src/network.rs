@@ -0,0 +1,151 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use git2::ProxyOptions;
+use reqwest::{Certificate, Client, ClientBuilder, Proxy, Url};
+
+use options::{cache_dir, expand_home, get_env};
+use verbose;
+use errors::*;
+
+#[derive(Debug, Default, Clone)]
+pub struct Network {
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// In the order `openssl-probe` looks for them
+const SYSTEM_CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/pki/tls/cacert.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/share/certs/ca-root-nss.crt",
+];
+
+/// Uses the git CLI, as libgit2 would initialize OpenSSL before the CA bundle is set up
+fn git_config(key: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["config", "--get", key])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if value.is_empty() { None } else { Some(value) }
+}
+
+pub fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    no_proxy.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|entry| entry.trim_start_matches('.'))
+        .map(|entry| match entry.rfind(':') {
+            Some(i) if entry[i + 1..].chars().all(|c| c.is_ascii_digit()) => &entry[..i],
+            _ => entry,
+        })
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host.eq_ignore_ascii_case(entry) || host.to_lowercase().ends_with(&format!(".{}", entry.to_lowercase())))
+}
+
+fn system_ca_bundle() -> Option<PathBuf> {
+    env::var_os("SSL_CERT_FILE").map(PathBuf::from).filter(|path| path.is_file())
+        .or_else(|| SYSTEM_CA_BUNDLES.iter().map(PathBuf::from).find(|path| path.is_file()))
+}
+
+/// `Certificate::from_pem` only reads the first certificate
+fn pem_certificates(bundle: &str) -> Vec<String> {
+    const END: &str = "-----END CERTIFICATE-----";
+    bundle.split(END)
+        .filter_map(|part| part.find("-----BEGIN CERTIFICATE-----").map(|i| format!("{}{}\n", &part[i..], END)))
+        .collect()
+}
+
+impl Network {
+    pub fn resolve(proxy: Option<String>, ca_bundle: Option<String>) -> Result<Network> {
+        let (proxy, source) = match proxy.map(|proxy| (proxy, "the config")).or_else(|| git_config("http.proxy").map(|proxy| (proxy, "git's http.proxy"))) {
+            Some((proxy, source)) => (Some(proxy), source),
+            None => (get_env(&["HTTPS_PROXY", "https_proxy"]), "the environment"),
+        };
+        verbose!(verbose::DEBUG, "proxy: {:?} (from {})", proxy, source);
+        let (ca_bundle, source) = match ca_bundle {
+            Some(ca_bundle) => (Some(ca_bundle), "the config"),
+            None => (git_config("http.sslCAInfo"), "git's http.sslCAInfo"),
+        };
+        verbose!(verbose::DEBUG, "ca_bundle: {:?} (from {})", ca_bundle, source);
+        let proxy = match proxy {
+            // Like git, assume HTTP when the proxy has no scheme
+            Some(ref proxy) if !proxy.contains("://") => Some(format!("http://{}", proxy)),
+            proxy => proxy,
+        };
+        if let Some(ref proxy) = proxy {
+            Url::parse(proxy).chain_err(|| ErrorKind::InvalidProxy(proxy.clone()))?;
+        }
+        Ok(Network {
+            proxy: proxy,
+            no_proxy: get_env(&["NO_PROXY", "no_proxy"]),
+            ca_bundle: ca_bundle.map(|path| expand_home(&path)),
+        })
+    }
+
+    pub fn proxy_for(&self, host: &str) -> Option<&str> {
+        match (self.proxy.as_ref(), self.no_proxy.as_ref()) {
+            (Some(_), Some(no_proxy)) if no_proxy_matches(no_proxy, host) => None,
+            (proxy, _) => proxy.map(|s| s.as_str()),
+        }
+    }
+
+    pub fn client_builder(&self) -> Result<ClientBuilder> {
+        let mut builder = Client::builder();
+        if self.proxy.is_some() {
+            let network = self.clone();
+            builder = builder.proxy(Proxy::custom(move |url| {
+                url.host_str().and_then(|host| network.proxy_for(host)).and_then(|proxy| Url::parse(proxy).ok())
+            }));
+        }
+        if let Some(ref path) = self.ca_bundle {
+            let bundle = fs::read_to_string(path).chain_err(|| ErrorKind::InvalidCABundle(path.display().to_string()))?;
+            for pem in pem_certificates(&bundle) {
+                let certificate = Certificate::from_pem(pem.as_bytes()).chain_err(|| ErrorKind::InvalidCABundle(path.display().to_string()))?;
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+
+    pub fn proxy_options<'a>(&self, url: &str) -> ProxyOptions<'a> {
+        let mut options = ProxyOptions::new();
+        let host = Url::parse(url).ok().and_then(|url| url.host_str().map(String::from));
+        if let Some(proxy) = host.and_then(|host| self.proxy_for(&host)) {
+            verbose!(verbose::TRACE, "Fetching {} through the proxy {}", url, proxy);
+            options.url(proxy);
+        }
+        options
+    }
+
+    /// OpenSSL only reads one bundle, so this combines the system's with the CA bundle.
+    /// It has to be called before anything else uses git2.
+    pub fn configure_git(&self) -> Result<()> {
+        let path = match self.ca_bundle {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut bundle = match system_ca_bundle() {
+            Some(system) => fs::read_to_string(system).unwrap_or_default(),
+            None => String::new(),
+        };
+        bundle.push('\n');
+        bundle.push_str(&fs::read_to_string(path).chain_err(|| ErrorKind::InvalidCABundle(path.display().to_string()))?);
+        let combined = cache_dir()?.join("ca-bundle.pem");
+        fs::create_dir_all(cache_dir()?)?;
+        fs::write(&combined, bundle)?;
+        verbose!(verbose::TRACE, "Combined the system's certificates and {} into {}", path.display(), combined.display());
+        env::set_var("SSL_CERT_FILE", &combined);
+        Ok(())
+    }
+}
@@ -1,15 +1,27 @@
 use std::io;
+use std::env;
+use std::fmt;
+use std::rc::Rc;
+use std::cell::RefCell;
 use std::fs::File;
-use std::path::PathBuf;
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap};
 
 use clap;
 use serde_yaml;
-use rpassword;
 use errors::*;
 use app_dirs2::{AppInfo, get_app_root, AppDataType};
 
 use repository::{Repository, Service, GitProtocol};
+use state::State;
+use output::OutputFormat;
+use credentials::{self, CredentialStore, StoredToken};
+use secrets::{self, Secret};
+use prompt;
+use verbose;
+use git_credential::GitCredential;
+use network::Network;
+use api::DEFAULT_API_RETRIES;
 
 const APP_INFO: AppInfo = AppInfo {
     name: "ghcl",
@@ -17,35 +29,129 @@ const APP_INFO: AppInfo = AppInfo {
 };
 
 #[derive(Debug, Clone)]
-pub struct Authentication {
-    pub username: String,
-    pub password: String,
+pub enum Authentication {
+    Token {
+        username: Option<String>,
+        token: Secret,
+    },
+    Basic {
+        username: String,
+        password: Secret,
+    },
 }
 
-#[derive(Debug)]
+impl Authentication {
+    pub fn username(&self) -> Option<&str> {
+        match *self {
+            Authentication::Token { ref username, .. } => username.as_ref().map(|s| s.as_str()),
+            Authentication::Basic { ref username, .. } => Some(username),
+        }
+    }
+
+    /// Tokens are used as the password, with any username
+    pub fn git_credentials(&self) -> (&str, &str) {
+        match *self {
+            Authentication::Token { ref username, ref token } => (username.as_ref().map(|s| s.as_str()).unwrap_or("x-access-token"), token.expose()),
+            Authentication::Basic { ref username, ref password } => (username, password.expose()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Options {
     pub repository: Repository,
+    pub profile: Option<String>,
     pub organization: Option<String>,
     pub track_upstream: bool,
     pub setup_upstream: bool,
     pub remote_name: String,
     pub origin_protocol: GitProtocol,
     pub upstream_protocol: GitProtocol,
-    pub authentication: Authentication,
+    pub ssh_key: Option<String>,
+    pub ssh_host: Option<String>,
+    #[serde(skip)]
+    pub credentials: Rc<Credentials>,
+    #[serde(skip)]
+    pub network: Network,
     pub clone_path: String,
     pub quiet: bool,
     pub fork_timeout: u64,
+    #[serde(skip)]
+    pub api_retries: u32,
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthAction {
+    Login,
+    Status,
+    Logout,
+}
+
+#[derive(Debug)]
+pub struct AuthOptions {
+    pub action: AuthAction,
+    pub service: Service,
+    pub oauth_client_id: Option<String>,
+    pub profile: Option<String>,
+    pub network: Network,
+    pub api_retries: u32,
 }
 
-#[derive(Debug, Deserialize)]
-struct PartialAuthentication {
+pub enum Command {
+    Clone(Options),
+    Resume(State),
+    Auth(AuthOptions),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PartialAuthentication {
     username: Option<String>,
-    password: Option<String>,
+    token: Option<Secret>,
+    password_command: Option<String>,
+    #[serde(default)]
+    keyring: bool,
+    password: Option<Secret>,
+    oauth_client_id: Option<String>,
+    /// The profile this was set in, whose secret store entry is read
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+impl PartialAuthentication {
+    fn take_token(&mut self, service: Service) -> Result<Option<Secret>> {
+        if let Some(token) = self.token.take() {
+            Ok(Some(token))
+        } else if let Some(ref command) = self.password_command {
+            Ok(Some(secrets::run_password_command(command)?))
+        } else if self.keyring {
+            Ok(Some(secrets::get(service, self.profile.as_ref().map(|s| s.as_str()))?.ok_or(ErrorKind::NotLoggedIn(service))?))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize)]
-struct Config {
+struct Profile {
+    #[serde(default)]
+    owners: Vec<String>,
+    #[serde(default)]
+    directories: Vec<String>,
     organization: Option<String>,
+    origin_protocol: Option<GitProtocol>,
+    upstream_protocol: Option<GitProtocol>,
+    clone_root: Option<String>,
+    ssh_key: Option<String>,
+    ssh_host: Option<String>,
+    #[serde(default)]
+    authentication: HashMap<Service, PartialAuthentication>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub organization: Option<String>,
     track_upstream: Option<bool>,
     setup_upstream: Option<bool>,
     remote_name: Option<String>,
@@ -54,23 +160,279 @@ struct Config {
     default_service: Option<Service>,
     quiet: Option<bool>,
     fork_timeout: Option<u64>,
+    api_retries: Option<u32>,
+    clone_root: Option<String>,
+    ssh_key: Option<String>,
+    ssh_host: Option<String>,
+    proxy: Option<String>,
+    ca_bundle: Option<String>,
     #[serde(default)]
-    authentication: HashMap<Service, PartialAuthentication>,
+    pub authentication: HashMap<Service, PartialAuthentication>,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
 }
 
-fn ask_for(prompt: &'static str, secure: bool) -> io::Result<String> {
-    if secure {
-        rpassword::prompt_password_stderr(prompt)
-    } else {
-        rpassword::prompt_response_stderr(prompt)
+impl Config {
+    /// Matches the upstream owner first, then the clone directory
+    pub fn find_profile(&self, owner: &str, target: &Path) -> Option<String> {
+        self.profiles.iter()
+            .find(|&(_, profile)| profile.owners.iter().any(|o| o.eq_ignore_ascii_case(owner)))
+            .or_else(|| self.profiles.iter().find(|&(_, profile)| profile.directories.iter().any(|dir| target.starts_with(expand_home(dir)))))
+            .map(|(name, _)| name.clone())
+    }
+
+    pub fn apply_profile(&mut self, name: &str) -> Result<()> {
+        let profile = self.profiles.remove(name).ok_or_else(|| ErrorKind::UnknownProfile(name.into()))?;
+        self.organization = profile.organization.or(self.organization.take());
+        self.origin_protocol = profile.origin_protocol.or(self.origin_protocol.take());
+        self.upstream_protocol = profile.upstream_protocol.or(self.upstream_protocol.take());
+        self.clone_root = profile.clone_root.or(self.clone_root.take());
+        self.ssh_key = profile.ssh_key.or(self.ssh_key.take());
+        self.ssh_host = profile.ssh_host.or(self.ssh_host.take());
+        self.authentication.extend(profile.authentication.into_iter().map(|(service, mut authentication)| {
+            authentication.profile = Some(name.into());
+            (service, authentication)
+        }));
+        Ok(())
+    }
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.starts_with("~/") || path == "~", env::var_os("HOME")) {
+        (true, Some(home)) => PathBuf::from(home).join(path[1..].trim_start_matches('/')),
+        _ => PathBuf::from(path),
+    }
+}
+
+pub fn get_env(names: &[&str]) -> Option<String> {
+    names.iter().filter_map(|name| env::var(name).ok()).find(|value| !value.is_empty())
+}
+
+/// Where credentials are looked up besides the options and config, which tests replace
+pub trait CredentialLookup {
+    fn env(&self, names: &[&str]) -> Option<String>;
+    fn stored_token(&self, service: Service, profile: Option<&str>) -> Result<Option<StoredToken>>;
+    fn gh_cli_token(&self, service: Service) -> Result<Option<StoredToken>>;
+    fn git_credential(&self, host: &str) -> Option<GitCredential>;
+    fn is_interactive(&self) -> bool;
+    fn prompt_token(&self) -> Result<Secret>;
+}
+
+pub struct SystemLookup;
+
+impl CredentialLookup for SystemLookup {
+    fn env(&self, names: &[&str]) -> Option<String> {
+        get_env(names)
+    }
+
+    fn stored_token(&self, service: Service, profile: Option<&str>) -> Result<Option<StoredToken>> {
+        CredentialStore::load()?.find(service, profile)
+    }
+
+    fn gh_cli_token(&self, service: Service) -> Result<Option<StoredToken>> {
+        credentials::gh_cli_token(service)
+    }
+
+    fn git_credential(&self, host: &str) -> Option<GitCredential> {
+        GitCredential::fill(host)
+    }
+
+    fn is_interactive(&self) -> bool {
+        prompt::is_interactive()
+    }
+
+    fn prompt_token(&self) -> Result<Secret> {
+        Ok(Secret::new(prompt::ask_for("Token: ", true)?))
     }
 }
 
-pub fn get_options() -> Result<Options> {
-    let matches = clap::App::new("ghcl")
+fn get_env_token<L: CredentialLookup>(lookup: &L, service: Service) -> Option<Secret> {
+    lookup.env(&["GHCL_TOKEN"]).or_else(|| lookup.env(service.token_env_vars())).map(Secret::new)
+}
+
+#[derive(Debug, Clone)]
+pub struct CredentialSources {
+    pub service: Service,
+    pub profile: Option<String>,
+    pub username: Option<String>,
+    pub token: Option<Secret>,
+    pub password: Option<Secret>,
+    pub config: Option<PartialAuthentication>,
+}
+
+/// Looked up when they're first needed
+#[derive(Debug, Default)]
+pub struct Credentials {
+    sources: Option<CredentialSources>,
+    authentication: RefCell<Option<Authentication>>,
+    git_credential: RefCell<Option<GitCredential>>,
+}
+
+impl Credentials {
+    pub fn get(&self) -> Result<Authentication> {
+        if let Some(ref authentication) = *self.authentication.borrow() {
+            return Ok(authentication.clone());
+        }
+        let sources = self.sources.clone().expect("credentials are set up by get_options");
+        let (authentication, git_credential) = resolve_credentials(sources, true, &SystemLookup)?;
+        *self.authentication.borrow_mut() = Some(authentication.clone());
+        *self.git_credential.borrow_mut() = git_credential;
+        Ok(authentication)
+    }
+
+    /// If this finds nothing, `get` looks them up again and may prompt
+    pub fn get_without_prompting(&self) -> Result<Option<Authentication>> {
+        if let Some(ref authentication) = *self.authentication.borrow() {
+            return Ok(Some(authentication.clone()));
+        }
+        let sources = self.sources.clone().expect("credentials are set up by get_options");
+        match resolve_credentials(sources, false, &SystemLookup) {
+            Ok((authentication, git_credential)) => {
+                *self.authentication.borrow_mut() = Some(authentication.clone());
+                *self.git_credential.borrow_mut() = git_credential;
+                Ok(Some(authentication))
+            }
+            Err(Error(ErrorKind::MissingCredentials(_), _)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn known(authentication: Authentication) -> Credentials {
+        Credentials {
+            sources: None,
+            authentication: RefCell::new(Some(authentication)),
+            git_credential: RefCell::new(None),
+        }
+    }
+
+    pub fn git_credential(&self) -> Option<GitCredential> {
+        self.git_credential.borrow().clone()
+    }
+}
+
+fn basic_authentication(service: Service, username: Option<String>, password: Secret, can_prompt: bool) -> Result<Authentication> {
+    let username = match username {
+        Some(username) => username,
+        None if can_prompt && prompt::is_interactive() => prompt::ask_for("Username: ", false)?,
+        None => Err(ErrorKind::MissingCredentials(service))?,
+    };
+    Ok(Authentication::Basic { username: username, password: password })
+}
+
+pub fn resolve_credentials<L: CredentialLookup>(sources: CredentialSources, can_prompt: bool, lookup: &L) -> Result<(Authentication, Option<GitCredential>)> {
+    let service = sources.service;
+    let mut config_auth = sources.config;
+    let username = sources.username.or_else(|| lookup.env(&["GHCL_USERNAME"])).or(config_auth.as_mut().and_then(|auth| auth.username.take()));
+    let config_password = config_auth.as_mut().and_then(|auth| auth.password.take());
+    let mut git_credential = None;
+    let (authentication, source) = if let Some(token) = sources.token {
+        (Authentication::Token { username: username, token: token }, "the --token option")
+    } else if let Some(password) = sources.password {
+        (basic_authentication(service, username, password, can_prompt)?, "the --password option")
+    } else if let Some(token) = get_env_token(lookup, service) {
+        (Authentication::Token { username: username, token: token }, "the environment")
+    } else if let Some(token) = match config_auth { Some(ref mut auth) => auth.take_token(service)?, None => None } {
+        (Authentication::Token { username: username, token: token }, "the config")
+    } else if let Some(password) = config_password {
+        (basic_authentication(service, username, password, can_prompt)?, "the config")
+    } else if let Some(stored) = lookup.stored_token(service, sources.profile.as_ref().map(|s| s.as_str()))? {
+        (Authentication::Token { username: username.or(stored.username), token: stored.token }, "ghcl's credential store")
+    } else if let Some(stored) = lookup.gh_cli_token(service)? {
+        (Authentication::Token { username: username.or(stored.username), token: stored.token }, "the gh CLI's config")
+    } else if let Some(credential) = lookup.git_credential(service.host()) {
+        let token = credential.password.clone();
+        git_credential = Some(credential);
+        (Authentication::Token { username: username, token: token }, "git's credential helpers")
+    } else if can_prompt && lookup.is_interactive() {
+        (Authentication::Token { username: username, token: lookup.prompt_token()? }, "the prompt")
+    } else {
+        Err(ErrorKind::MissingCredentials(service))?
+    };
+    verbose!(verbose::DEBUG, "Using credentials for {} from {}", service.host(), source);
+    Ok((authentication, git_credential))
+}
+
+fn get_credentials(matches: &clap::ArgMatches, config: &mut Config, service: Service, profile: Option<&str>) -> Rc<Credentials> {
+    Rc::new(Credentials {
+        sources: Some(CredentialSources {
+            service: service,
+            profile: profile.map(String::from),
+            username: matches.value_of("username").map(String::from),
+            token: matches.value_of("token").map(|token| Secret::new(token.into())),
+            password: matches.value_of("password").map(|password| Secret::new(password.into())),
+            config: config.authentication.remove(&service),
+        }),
+        authentication: RefCell::new(None),
+        git_credential: RefCell::new(None),
+    })
+}
+
+fn get_auth_options(matches: &clap::ArgMatches, config: &mut Config, profile: Option<&str>, network: Network) -> Result<AuthOptions> {
+    let (action, matches) = match matches.subcommand() {
+        ("login", Some(matches)) => (AuthAction::Login, matches),
+        ("status", Some(matches)) => (AuthAction::Status, matches),
+        ("logout", Some(matches)) => (AuthAction::Logout, matches),
+        _ => unreachable!("clap requires an auth subcommand"),
+    };
+    let service = match matches.value_of("service") {
+        Some("github") | Some("GitHub") | Some("Github") => Service::GitHub,
+        _ => config.default_service.unwrap_or(Service::GitHub),
+    };
+    let config_client_id = config.authentication.remove(&service).and_then(|auth| auth.oauth_client_id);
+    Ok(AuthOptions {
+        action: action,
+        service: service,
+        oauth_client_id: matches.value_of("client_id").map(String::from).or_else(|| get_env(&["GHCL_OAUTH_CLIENT_ID"])).or(config_client_id),
+        profile: profile.map(String::from),
+        network: network,
+        api_retries: config.api_retries.unwrap_or(DEFAULT_API_RETRIES),
+    })
+}
+
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(get_app_root(AppDataType::UserConfig, &APP_INFO)?)
+}
+
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(get_app_root(AppDataType::UserCache, &APP_INFO)?)
+}
+
+fn service_arg() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("service")
+        .short("s")
+        .long("service")
+        .value_name("SERVICE")
+        .possible_values(&["github", "GitHub", "Github"])
+        .help("The service to use (default: the default service)")
+}
+
+pub fn get_matches() -> clap::ArgMatches<'static> {
+    clap::App::new("ghcl")
         .version("0.1.0")
         .author("Lee Bousfield <ljbousfield@gmail.com>")
         .about("Automatically forks and clones a GitHub repository")
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(clap::SubCommand::with_name("auth")
+             .about("Manages the tokens saved for each service")
+             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+             .subcommand(clap::SubCommand::with_name("login")
+                  .about("Logs in through the browser, and saves the token for later runs")
+                  .arg(service_arg())
+                  .arg(clap::Arg::with_name("client_id")
+                       .long("client-id")
+                       .value_name("CLIENT_ID")
+                       .help("The client ID of the OAuth app to log in with (default: oauth_client_id in the config)")))
+             .subcommand(clap::SubCommand::with_name("status")
+                  .about("Checks the saved token")
+                  .arg(service_arg()))
+             .subcommand(clap::SubCommand::with_name("logout")
+                  .about("Removes the saved token")
+                  .arg(service_arg())))
+        .subcommand(clap::SubCommand::with_name("resume")
+             .about("Picks up an interrupted or failed run where it left off")
+             .arg(clap::Arg::with_name("path")
+                  .value_name("PATH")
+                  .help("The partially cloned repository (defaults to the current directory)")))
         .arg(clap::Arg::with_name("repository")
              .value_name("REPOSITORY")
              .required(true)
@@ -83,6 +445,10 @@ pub fn get_options() -> Result<Options> {
              .long("config")
              .value_name("FILE")
              .help("Sets a custom config file"))
+        .arg(clap::Arg::with_name("profile")
+             .long("profile")
+             .value_name("PROFILE")
+             .help("The profile from the config to use (default: the one matching the repository owner or directory, if any)"))
         .arg(clap::Arg::with_name("organization")
              .short("o")
              .long("organization")
@@ -93,11 +459,17 @@ pub fn get_options() -> Result<Options> {
              .long("username")
              .value_name("USERNAME")
              .help("Your username"))
+        .arg(clap::Arg::with_name("token")
+             .short("t")
+             .long("token")
+             .value_name("TOKEN")
+             .help("Your API token (insecure - put it in your config or the environment, or input it when prompted)"))
         .arg(clap::Arg::with_name("password")
              .short("p")
              .long("password")
              .value_name("PASSWORD")
-             .help("Your password (insecure - use a personal access token and put it in your config, or input your password when prompted)"))
+             .conflicts_with("token")
+             .help("Your password, for basic auth (insecure - prefer a token)"))
         .arg(clap::Arg::with_name("fork_timeout")
              .long("fork-timeout")
              .value_name("TIMEOUT")
@@ -136,6 +508,11 @@ pub fn get_options() -> Result<Options> {
              .long("no-upstream")
              .conflicts_with_all(&["upstream", "track_upstream", "remote_name"])
              .help("Don't setup an upstream remote (implies no_track_upstream)"))
+        .arg(clap::Arg::with_name("verbose")
+             .short("v")
+             .long("verbose")
+             .multiple(true)
+             .help("Log API requests, git operations and where options came from (-vv to also log each credential method tried)"))
         .arg(clap::Arg::with_name("quiet")
              .short("q")
              .long("quiet")
@@ -144,18 +521,56 @@ pub fn get_options() -> Result<Options> {
              .long("no-quiet")
              .conflicts_with_all(&["quiet"])
              .help("Don't be quiet (output status messages)"))
-        .get_matches();
+        .arg(clap::Arg::with_name("dry_run")
+             .long("dry-run")
+             .help("Print what would be done, without forking or cloning anything"))
+        .arg(clap::Arg::with_name("non_interactive")
+             .long("non-interactive")
+             .help("Never prompt, failing instead if credentials are missing (default when stdin isn't a terminal)"))
+        .arg(clap::Arg::with_name("output")
+             .long("output")
+             .value_name("FORMAT")
+             .possible_values(&["text", "json"])
+             .help("The format of the result printed to stdout (default: text)"))
+        .get_matches()
+}
+
+pub fn get_output_format(matches: &clap::ArgMatches) -> OutputFormat {
+    match matches.value_of("output") {
+        Some("json") => OutputFormat::Json,
+        _ => OutputFormat::Text,
+    }
+}
+
+fn pick_optional<T: fmt::Debug>(name: &str, cli: Option<T>, config: Option<T>, default: Option<T>) -> Option<T> {
+    let (value, source) = match (cli, config) {
+        (Some(value), _) => (Some(value), "the command line"),
+        (None, Some(value)) => (Some(value), "the config"),
+        (None, None) => (default, "the default"),
+    };
+    verbose!(verbose::DEBUG, "{}: {:?} (from {})", name, value, source);
+    value
+}
+
+fn pick<T: fmt::Debug>(name: &str, cli: Option<T>, config: Option<T>, default: T) -> T {
+    pick_optional(name, cli, config, Some(default)).expect("a default was given")
+}
+
+pub fn get_options(matches: &clap::ArgMatches) -> Result<Command> {
     let config_path: Result<PathBuf> = matches.value_of("config").map(PathBuf::from).map(Ok).unwrap_or_else(|| {
-        let mut app_dir = get_app_root(AppDataType::UserConfig, &APP_INFO)?;
+        let mut app_dir = config_dir()?;
         app_dir.push("config.yml");
         Ok(app_dir)
     });
     let config_path = config_path?;
-    let mut config = match File::open(config_path) {
+    verbose!(verbose::DEBUG, "Reading the config from {}", config_path.display());
+    let mut config: Config = match File::open(config_path) {
         Ok(file) => serde_yaml::from_reader(file)?,
         Err(ref err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
         Err(err) => Err(err)?,
     };
+    let network = Network::resolve(config.proxy.take(), config.ca_bundle.take())?;
+    network.configure_git()?;
     let matches_track_upstream = if matches.is_present("track_upstream") {
         Some(true)
     } else if matches.is_present("no_track_upstream") || matches.is_present("no_upstream") {
@@ -177,6 +592,31 @@ pub fn get_options() -> Result<Options> {
     } else {
         None
     };
+    if let Some(auth_matches) = matches.subcommand_matches("auth") {
+        if let Some(profile) = matches.value_of("profile") {
+            config.apply_profile(profile)?;
+        }
+        return get_auth_options(auth_matches, &mut config, matches.value_of("profile"), network).map(Command::Auth);
+    }
+    if let Some(resume_matches) = matches.subcommand_matches("resume") {
+        let mut state = State::load(resume_matches.value_of("path").unwrap_or("."))?;
+        if let Some(profile) = matches.value_of("profile") {
+            state.options.profile = Some(profile.into());
+        }
+        if let Some(ref profile) = state.options.profile {
+            verbose!(verbose::DEBUG, "Using the {} profile", profile);
+            config.apply_profile(profile)?;
+        }
+        state.options.credentials = get_credentials(matches, &mut config, state.options.repository.service, state.options.profile.as_ref().map(|s| s.as_str()));
+        if let Some(quiet) = matches_quiet {
+            state.options.quiet = quiet;
+        }
+        state.options.network = network;
+        state.options.api_retries = config.api_retries.unwrap_or(DEFAULT_API_RETRIES);
+        state.options.dry_run = matches.is_present("dry_run");
+        verbose!(verbose::DEBUG, "Resuming with the saved options: {:?}", state.options);
+        return Ok(Command::Resume(state));
+    }
     if matches_track_upstream != Some(false) && config.track_upstream == Some(true) && config.setup_upstream == Some(false) {
         Err(ErrorKind::ConfigTrackNoSetup)?
     }
@@ -190,28 +630,45 @@ pub fn get_options() -> Result<Options> {
         Some("ssh") | Some("SSH") => Some(GitProtocol::SSH),
         _ => None,
     };
-    let track_upstream = matches_track_upstream.or(config.track_upstream).or(config.setup_upstream).unwrap_or(true);
+    let track_upstream = pick("track_upstream", matches_track_upstream, config.track_upstream.or(config.setup_upstream), true);
     let matches_default_service = match matches.value_of("default_service") {
         Some("github") | Some("GitHub") | Some("Github") => Some(Service::GitHub),
         _ => None,
     };
-    let repository = Repository::from_arg_string(matches.value_of("repository").unwrap(), matches_default_service.or(config.default_service).unwrap_or(Service::GitHub))?;
-    let service = repository.service;
-    let mut config_auth = config.authentication.remove(&service);
-    let username = matches.value_of("username").map(String::from).or(config_auth.as_mut().and_then(|auth| auth.username.take())).map(Ok).unwrap_or_else(|| ask_for("Username: ", false))?;
-    let password = matches.value_of("password").map(String::from).or(config_auth.as_mut().and_then(|auth| auth.password.take())).map(Ok).unwrap_or_else(|| ask_for("Password: ", true))?;
-    let clone_path = matches.value_of("clone_path").unwrap_or(&repository.name).into();
-    Ok(Options {
+    let repository = Repository::from_arg_string(matches.value_of("repository").unwrap(), pick("default_service", matches_default_service, config.default_service, Service::GitHub))?;
+    let profile = match matches.value_of("profile") {
+        Some(profile) => Some(profile.to_string()),
+        None => config.find_profile(&repository.user, &env::current_dir()?.join(matches.value_of("clone_path").unwrap_or(""))),
+    };
+    verbose!(verbose::DEBUG, "profile: {:?} (from {})", profile, if matches.is_present("profile") { "the command line" } else { "matching the owner or directory" });
+    if let Some(ref profile) = profile {
+        config.apply_profile(profile)?;
+    }
+    let credentials = get_credentials(matches, &mut config, repository.service, profile.as_ref().map(|s| s.as_str()));
+    let clone_path = pick("clone_path", matches.value_of("clone_path").map(String::from),
+        config.clone_root.map(|clone_root| expand_home(&clone_root).join(&repository.name).to_string_lossy().into_owned()),
+        repository.name.clone());
+    let matches_fork_timeout = match matches.value_of("fork_timeout") {
+        Some(fork_timeout) => Some(fork_timeout.parse()?),
+        None => None,
+    };
+    Ok(Command::Clone(Options {
         repository: repository,
-        organization: matches.value_of("organization").map(String::from).or(config.organization),
+        profile: profile,
+        organization: pick_optional("organization", matches.value_of("organization").map(String::from), config.organization, None),
         track_upstream: track_upstream,
-        setup_upstream: track_upstream || matches_setup_upstream.or(config.setup_upstream).unwrap_or(true),
-        remote_name: matches.value_of("remote_name").map(String::from).or(config.remote_name).unwrap_or_else(|| "upstream".into()),
-        origin_protocol: matches_origin_protocol.or(config.origin_protocol).unwrap_or(GitProtocol::SSH),
-        upstream_protocol: matches_upstream_protocol.or(config.upstream_protocol).unwrap_or(GitProtocol::HTTPS),
-        authentication: Authentication { username: username, password: password },
+        setup_upstream: track_upstream || pick("setup_upstream", matches_setup_upstream, config.setup_upstream, true),
+        remote_name: pick("remote_name", matches.value_of("remote_name").map(String::from), config.remote_name, "upstream".into()),
+        origin_protocol: pick("origin_protocol", matches_origin_protocol, config.origin_protocol, GitProtocol::SSH),
+        upstream_protocol: pick("upstream_protocol", matches_upstream_protocol, config.upstream_protocol, GitProtocol::HTTPS),
+        ssh_key: pick_optional("ssh_key", None, config.ssh_key, None),
+        ssh_host: pick_optional("ssh_host", None, config.ssh_host, None),
+        credentials: credentials,
+        network: network,
         clone_path: clone_path,
-        quiet: matches_quiet.or(config.quiet).unwrap_or(false),
-        fork_timeout: matches.value_of("fork_timeout").map(|s| s.parse()).or(config.fork_timeout.map(Ok)).unwrap_or(Ok(30))?,
-    })
+        quiet: pick("quiet", matches_quiet, config.quiet, false),
+        fork_timeout: pick("fork_timeout", matches_fork_timeout, config.fork_timeout, 30),
+        api_retries: pick("api_retries", None, config.api_retries, DEFAULT_API_RETRIES),
+        dry_run: matches.is_present("dry_run"),
+    }))
 }
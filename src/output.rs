@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+use std::time::Duration;
+
+use git2;
+use serde::Serialize;
+use serde_json;
+
+use secrets;
+use repository::Service;
+use errors::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Remote {
+    pub name: String,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrackedBranch {
+    pub branch: String,
+    pub upstream: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Timings {
+    pub fork: Option<f64>,
+    pub clone: Option<f64>,
+    pub upstream: Option<f64>,
+    pub total: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub fork_url: Option<String>,
+    pub fork_existed: Option<bool>,
+    pub clone_path: String,
+    pub remotes: Vec<Remote>,
+    pub tracked_branch: Option<TrackedBranch>,
+    pub timings: Timings,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedFork {
+    pub owner: String,
+    pub existed: bool,
+    pub can_push: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlannedClone {
+    pub url: String,
+    pub path: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub profile: Option<String>,
+    pub fork: Option<PlannedFork>,
+    pub clone: Option<PlannedClone>,
+    pub upstream: Option<Remote>,
+    pub tracked_branch: Option<TrackedBranch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuthStatus<'a> {
+    pub service: Service,
+    pub profile: Option<&'a str>,
+    pub username: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorOutput<'a> {
+    error: ErrorReport<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorReport<'a> {
+    code: &'static str,
+    message: String,
+    causes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resume_path: Option<&'a str>,
+}
+
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+impl Report {
+    pub fn describe_repo(&mut self, repo: &git2::Repository) -> Result<()> {
+        for name in repo.remotes()?.iter().flatten() {
+            let remote = repo.find_remote(name)?;
+            self.remotes.push(Remote {
+                name: name.to_string(),
+                url: remote.url().map(String::from),
+            });
+        }
+        if let Ok(head) = repo.head() {
+            let branch = git2::Branch::wrap(head);
+            if let (Ok(Some(name)), Ok(upstream)) = (branch.name(), branch.upstream()) {
+                if let Ok(Some(upstream_name)) = upstream.name() {
+                    self.tracked_branch = Some(TrackedBranch {
+                        branch: name.to_string(),
+                        upstream: upstream_name.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print(&self) -> Result<()> {
+        print_json(self)
+    }
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, value).chain_err(|| "failed to write JSON output")?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+impl Plan {
+    pub fn print(&self) -> Result<()> {
+        print_json(self)
+    }
+}
+
+pub fn print_error(err: &Error, resume_path: Option<&str>) -> io::Result<()> {
+    let output = ErrorOutput {
+        error: ErrorReport {
+            code: err.specific_kind().code(),
+            message: secrets::scrub(&err.to_string()),
+            causes: err.iter().skip(1).map(|e| secrets::scrub(&e.to_string())).collect(),
+            resume_path: resume_path,
+        },
+    };
+    let mut stdout = io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, &output)?;
+    writeln!(stdout)
+}
@@ -0,0 +1,50 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use atty;
+use rpassword;
+
+use interrupt;
+use errors::*;
+
+static INTERACTIVE: AtomicBool = AtomicBool::new(true);
+
+const NO_PROMPT: usize = 0;
+const PLAIN_PROMPT: usize = 1;
+const SECURE_PROMPT: usize = 2;
+
+static ACTIVE_PROMPT: AtomicUsize = AtomicUsize::new(NO_PROMPT);
+
+pub fn set_interactive(interactive: bool) {
+    INTERACTIVE.store(interactive && atty::is(atty::Stream::Stdin), Ordering::SeqCst);
+}
+
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::SeqCst)
+}
+
+pub fn is_prompting() -> bool {
+    ACTIVE_PROMPT.load(Ordering::SeqCst) != NO_PROMPT
+}
+
+pub fn is_prompting_securely() -> bool {
+    ACTIVE_PROMPT.load(Ordering::SeqCst) == SECURE_PROMPT
+}
+
+/// Ctrl-C doesn't interrupt reading the response, so this fails once it's entered if ghcl was interrupted
+pub fn ask_for(prompt: &str, secure: bool) -> Result<String> {
+    interrupt::check()?;
+    ACTIVE_PROMPT.store(if secure { SECURE_PROMPT } else { PLAIN_PROMPT }, Ordering::SeqCst);
+    let response = if secure {
+        rpassword::prompt_password_stderr(prompt)
+    } else {
+        rpassword::prompt_response_stderr(prompt)
+    };
+    ACTIVE_PROMPT.store(NO_PROMPT, Ordering::SeqCst);
+    interrupt::check()?;
+    Ok(response?)
+}
+
+pub fn notify_interrupted() {
+    writeln!(io::stderr(), "\nInterrupted, press Enter to stop").ok();
+}
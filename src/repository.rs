@@ -1,48 +1,149 @@
 use serde_json;
 use regex::Regex;
-use reqwest::{self, Client, StatusCode, Response};
+use reqwest::{StatusCode, Response};
 use serde::de::DeserializeOwned;
 
-use options::Authentication;
+use api::{self, Api};
+use github;
+use secrets;
 use errors::*;
 
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Service {
     GitHub,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+impl Service {
+    pub fn token_env_vars(&self) -> &'static [&'static str] {
+        match *self {
+            Service::GitHub => &["GH_TOKEN", "GITHUB_TOKEN"],
+        }
+    }
+
+    pub fn host(&self) -> &'static str {
+        match *self {
+            Service::GitHub => "github.com",
+        }
+    }
+
+    pub fn api_url(&self) -> &'static str {
+        match *self {
+            Service::GitHub => "https://api.github.com",
+        }
+    }
+
+    pub fn get_username(&self, api: &Api) -> Result<String> {
+        if let Some(username) = api.authentication()?.username() {
+            return Ok(username.to_string());
+        }
+        match *self {
+            Service::GitHub => {
+                let user: github::User = github_res(api.get_authenticated("/user")?)?;
+                Ok(user.login)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Repository {
     pub service: Service,
     pub user: String,
     pub name: String,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub enum GitProtocol {
     SSH,
     HTTPS,
 }
 
-fn github_repo_json_git_url(json: serde_json::Value, git_protocol: GitProtocol) -> Result<String> {
-    Ok(match git_protocol {
-        GitProtocol::SSH => json.get("ssh_url").ok_or(ErrorKind::MissingKey("ssh_url"))?.as_str().ok_or(ErrorKind::MalformedKey("ssh_url"))?.into(),
-        GitProtocol::HTTPS => json.get("clone_url").ok_or(ErrorKind::MissingKey("clone_url"))?.as_str().ok_or(ErrorKind::MalformedKey("clone_url"))?.into(),
-    })
+#[derive(Debug)]
+pub struct ForkPlan {
+    pub owner: String,
+    pub git_url: String,
+    pub existing: Option<github::Repo>,
+}
+
+#[derive(Debug)]
+pub struct Fork {
+    pub git_url: String,
+    pub existed: Option<bool>,
 }
 
-fn github_res<D: DeserializeOwned>(mut res: Response) -> Result<D> {
+/// A fork older than this, in seconds by GitHub's clock, already existed
+const NEW_FORK_MAX_AGE: u64 = 60;
+
+const MAX_ERROR_BODY_CHARS: usize = 200;
+
+impl github::Repo {
+    pub fn git_url(&self, git_protocol: GitProtocol) -> String {
+        match git_protocol {
+            GitProtocol::SSH => self.ssh_url.clone(),
+            GitProtocol::HTTPS => self.clone_url.clone(),
+        }
+    }
+
+    pub fn is_fork_of(&self, full_name: &str) -> bool {
+        self.parent.as_ref().map_or(false, |parent| parent.full_name.eq_ignore_ascii_case(full_name))
+    }
+}
+
+fn github_git_url(owner: &str, name: &str, git_protocol: GitProtocol) -> String {
+    match git_protocol {
+        GitProtocol::SSH => format!("git@github.com:{}/{}.git", owner, name),
+        GitProtocol::HTTPS => format!("https://github.com/{}/{}.git", owner, name),
+    }
+}
+
+/// Only classic tokens list their scopes, so other credentials are checked when the API rejects a request
+fn github_check_scopes(api: &Api) -> Result<()> {
+    let res = api.get_authenticated("/user")?;
+    let scopes = match res.headers().get("X-OAuth-Scopes").and_then(|value| value.to_str().ok()) {
+        Some(scopes) => scopes.split(',').map(|scope| scope.trim().to_string()).collect::<Vec<_>>(),
+        None => return github_res::<github::User>(res).map(|_| ()),
+    };
+    if !scopes.iter().any(|scope| scope == "repo" || scope == "public_repo") {
+        Err(ErrorKind::InsufficientPermissions("repo (or public_repo, for public repositories)".into()))?
+    }
+    Ok(())
+}
+
+fn body_excerpt(body: &str) -> &str {
+    let body = body.trim();
+    match body.char_indices().nth(MAX_ERROR_BODY_CHARS) {
+        Some((i, _)) => &body[..i],
+        None => body,
+    }
+}
+
+pub fn github_res<D: DeserializeOwned>(mut res: Response) -> Result<D> {
     match res.status() {
         StatusCode::OK | StatusCode::CREATED | StatusCode::ACCEPTED | StatusCode::NO_CONTENT => {
-            Ok(res.json()?)
+            let body = res.text()?;
+            Ok(serde_json::from_str(&body).map_err(|err| ErrorKind::MalformedResponse(err.to_string()))?)
+        }
+        StatusCode::FORBIDDEN if res.headers().contains_key("X-Accepted-GitHub-Permissions") => {
+            // Fine-grained tokens and apps are told which permissions the endpoint needs
+            let permissions = res.headers()["X-Accepted-GitHub-Permissions"].to_str().unwrap_or("unknown").to_string();
+            Err(ErrorKind::InsufficientPermissions(permissions).into())
         }
-        _ => {
-            let json: serde_json::Value = res.json()?;
-            if let Some(message) = json.get("message").and_then(|v| v.as_str()) {
-                return Err(ErrorKind::APIError(message.into()).into());
+        status => {
+            let body = res.text()?;
+            let json: serde_json::Value = match serde_json::from_str(&body) {
+                Ok(json) => json,
+                // Such as an HTML error page from a proxy
+                Err(_) => return Err(ErrorKind::APIError(format!("{} {}", status, secrets::scrub(body_excerpt(&body)))).into()),
+            };
+            let error: github::ApiError = match serde_json::from_value(json.clone()) {
+                Ok(error) => error,
+                Err(_) => return Err(ErrorKind::RawAPIError(json).into()),
+            };
+            if status == StatusCode::UNAUTHORIZED {
+                return Err(ErrorKind::AuthenticationFailed(error.to_string()).into());
             }
-            Err(ErrorKind::RawAPIError(json).into())
+            Err(ErrorKind::APIError(error.to_string()).into())
         }
     }
 }
@@ -88,30 +189,57 @@ impl Repository {
         })
     }
 
-    pub fn get_git_url(&self, git_protocol: GitProtocol) -> Result<String> {
+    pub fn get(&self, api: &Api) -> Result<github::Repo> {
+        match self.service {
+            Service::GitHub => github_res(api.get(&format!("/repos/{}/{}", self.user, self.name))?),
+        }
+    }
+
+    pub fn get_git_url(&self, api: &Api, git_protocol: GitProtocol) -> Result<String> {
+        Ok(self.get(api)?.git_url(git_protocol))
+    }
+
+    pub fn get_default_branch(&self, api: &Api) -> Result<String> {
+        Ok(self.get(api)?.default_branch)
+    }
+
+    pub fn plan_fork(&self, api: &Api, organization: Option<&str>, git_protocol: GitProtocol) -> Result<ForkPlan> {
         match self.service {
             Service::GitHub => {
-                let res = reqwest::get(&format!("https://api.github.com/repos/{}/{}", self.user, self.name))?;
-                let json = github_res(res)?;
-                Ok(github_repo_json_git_url(json, git_protocol).chain_err(|| "failed to get git URL from JSON")?)
+                let owner = match organization {
+                    Some(org) => org.to_string(),
+                    None => self.service.get_username(api)?,
+                };
+                let res = api.get(&format!("/repos/{}/{}", owner, self.name))?;
+                let existing = if res.status() == StatusCode::NOT_FOUND {
+                    None
+                } else {
+                    let repo: github::Repo = github_res(res)?;
+                    // A repository with the same name which isn't a fork of this one is left alone, and the fork gets another name
+                    if repo.is_fork_of(&format!("{}/{}", self.user, self.name)) { Some(repo) } else { None }
+                };
+                Ok(ForkPlan {
+                    git_url: existing.as_ref().map(|repo| repo.git_url(git_protocol.clone())).unwrap_or_else(|| github_git_url(&owner, &self.name, git_protocol)),
+                    owner: owner,
+                    existing: existing,
+                })
             }
         }
     }
 
-    pub fn fork(&self, authentication: Authentication, organization: Option<&str>, git_protocol: GitProtocol) -> Result<String> {
-        let http_client = Client::new();
+    pub fn fork(&self, api: &Api, organization: Option<&str>, git_protocol: GitProtocol) -> Result<Fork> {
         match self.service {
             Service::GitHub => {
-                let mut params_map = serde_json::Map::new();
-                if let Some(org) = organization {
-                    params_map.insert("organization".into(), serde_json::Value::String(org.into()));
-                }
-                let res = http_client.post(&format!("https://api.github.com/repos/{}/{}/forks", self.user, self.name))
-                    .json(&serde_json::Value::Object(params_map))
-                    .basic_auth(authentication.username, Some(authentication.password))
-                    .send()?;
-                let json = github_res(res)?;
-                Ok(github_repo_json_git_url(json, git_protocol).chain_err(|| "failed to get git URL from JSON")?)
+                github_check_scopes(api).chain_err(|| "failed to check the token's permissions")?;
+                let request = github::ForkRequest { organization: organization };
+                // Forking a repository that's already been forked returns the existing fork, so this can be retried
+                let res = api.post(&format!("/repos/{}/{}/forks", self.user, self.name), &request, true)?;
+                let date = api::response_date(res.headers());
+                let fork: github::Repo = github_res(res)?;
+                Ok(Fork {
+                    existed: date.and_then(|date| fork.created_at_unix().map(|created| date.saturating_sub(created) > NEW_FORK_MAX_AGE)),
+                    git_url: fork.git_url(git_protocol),
+                })
             }
         }
     }
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use options::expand_home;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SshUrl<'a> {
+    pub user: Option<&'a str>,
+    pub host: &'a str,
+    pub port: Option<&'a str>,
+    pub path: &'a str,
+}
+
+fn split_user(authority: &str) -> (Option<&str>, &str) {
+    match authority.rfind('@') {
+        Some(i) => (Some(&authority[..i]), &authority[i + 1..]),
+        None => (None, authority),
+    }
+}
+
+impl<'a> SshUrl<'a> {
+    pub fn parse(url: &'a str) -> Option<SshUrl<'a>> {
+        if url.starts_with("ssh://") {
+            let rest = &url["ssh://".len()..];
+            let slash = rest.find('/')?;
+            let (user, host_port) = split_user(&rest[..slash]);
+            let (host, port) = match host_port.find(':') {
+                Some(i) => (&host_port[..i], Some(&host_port[i + 1..])),
+                None => (host_port, None),
+            };
+            Some(SshUrl { user: user, host: host, port: port, path: &rest[slash + 1..] })
+        } else if url.contains("://") {
+            None
+        } else {
+            let colon = url.find(':')?;
+            if url[..colon].contains('/') {
+                return None; // a local path
+            }
+            let (user, host) = split_user(&url[..colon]);
+            Some(SshUrl { user: user, host: host, port: None, path: &url[colon + 1..] })
+        }
+    }
+}
+
+impl<'a> fmt::Display for SshUrl<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let user = self.user.map(|user| format!("{}@", user)).unwrap_or_default();
+        match self.port {
+            Some(port) => write!(f, "ssh://{}{}:{}/{}", user, self.host, port, self.path),
+            None => write!(f, "{}{}:{}", user, self.host, self.path),
+        }
+    }
+}
+
+pub fn with_host(url: &str, host: &str) -> String {
+    match SshUrl::parse(url) {
+        Some(ssh_url) => SshUrl { host: host, ..ssh_url }.to_string(),
+        None => url.into(),
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HostConfig {
+    pub host_name: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<String>,
+    pub identity_files: Vec<String>,
+}
+
+fn matches_pattern(pattern: &[u8], host: &[u8]) -> bool {
+    match (pattern.first(), host.first()) {
+        (None, None) => true,
+        (Some(&b'*'), _) => matches_pattern(&pattern[1..], host) || (!host.is_empty() && matches_pattern(pattern, &host[1..])),
+        (Some(&b'?'), Some(_)) => matches_pattern(&pattern[1..], &host[1..]),
+        (Some(p), Some(h)) => p.eq_ignore_ascii_case(h) && matches_pattern(&pattern[1..], &host[1..]),
+        _ => false,
+    }
+}
+
+pub fn host_matches(patterns: &str, host: &str) -> bool {
+    let mut matched = false;
+    for pattern in patterns.split_whitespace() {
+        if pattern.starts_with('!') {
+            if matches_pattern(pattern[1..].as_bytes(), host.as_bytes()) {
+                return false;
+            }
+        } else if matches_pattern(pattern.as_bytes(), host.as_bytes()) {
+            matched = true;
+        }
+    }
+    matched
+}
+
+/// As with ssh, the first value of each setting wins, except `IdentityFile`.
+/// `Match` and `Include` aren't supported.
+pub fn parse(config: &str, host: &str) -> HostConfig {
+    let mut host_config = HostConfig::default();
+    let mut applies = true;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let split = line.find(|c: char| c.is_whitespace() || c == '=').unwrap_or_else(|| line.len());
+        let keyword = line[..split].to_lowercase();
+        let value = line[split..].trim_start_matches(|c: char| c.is_whitespace() || c == '=').trim_matches('"');
+        match keyword.as_str() {
+            "host" => applies = host_matches(value, host),
+            "match" => applies = false,
+            _ if !applies => {}
+            "hostname" if host_config.host_name.is_none() => host_config.host_name = Some(value.into()),
+            "user" if host_config.user.is_none() => host_config.user = Some(value.into()),
+            "port" if host_config.port.is_none() => host_config.port = Some(value.into()),
+            "identityfile" => host_config.identity_files.push(value.into()),
+            _ => {}
+        }
+    }
+    host_config
+}
+
+pub fn load(host: &str) -> HostConfig {
+    match fs::read_to_string(expand_home("~/.ssh/config")) {
+        Ok(config) => parse(&config, host),
+        Err(_) => HostConfig::default(),
+    }
+}
+
+/// libgit2 doesn't read `~/.ssh/config`, so the URL is rewritten to the host's real name, user and port
+pub fn resolve(url: &str) -> (String, Vec<PathBuf>) {
+    let ssh_url = match SshUrl::parse(url) {
+        Some(ssh_url) => ssh_url,
+        None => return (url.into(), Vec::new()),
+    };
+    let host_config = load(ssh_url.host);
+    let identity_files = host_config.identity_files.iter()
+        .filter(|path| !path.contains('%'))
+        .map(|path| expand_home(path))
+        .collect();
+    let resolved = SshUrl {
+        user: ssh_url.user.or(host_config.user.as_ref().map(|s| s.as_str())),
+        host: host_config.host_name.as_ref().map(|s| s.as_str()).unwrap_or(ssh_url.host),
+        port: ssh_url.port.or(host_config.port.as_ref().map(|s| s.as_str())),
+        path: ssh_url.path,
+    };
+    (resolved.to_string(), identity_files)
+}
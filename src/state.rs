@@ -0,0 +1,63 @@
+use std::io;
+use std::fs::{self, File};
+use std::path::Path;
+
+use git2;
+use serde_yaml;
+
+use options::Options;
+use errors::*;
+
+/// Relative to the clone's `.git` directory
+const STATE_FILE: &str = "ghcl-state.yml";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct State {
+    pub options: Options,
+    pub fork_git_url: Option<String>,
+    pub cloned: bool,
+    pub upstream_added: bool,
+    pub upstream_tracked: bool,
+}
+
+impl State {
+    pub fn new(options: Options) -> State {
+        State {
+            options: options,
+            fork_git_url: None,
+            cloned: false,
+            upstream_added: false,
+            upstream_tracked: false,
+        }
+    }
+
+    /// The credentials are not saved, and must be filled in by the caller
+    pub fn load<P: AsRef<Path>>(clone_path: P) -> Result<State> {
+        let clone_path = clone_path.as_ref();
+        let repo = match git2::Repository::discover(clone_path) {
+            Ok(repo) => repo,
+            Err(ref err) if err.code() == git2::ErrorCode::NotFound => Err(ErrorKind::NothingToResume(clone_path.display().to_string()))?,
+            Err(err) => Err(err)?,
+        };
+        let file = match File::open(repo.path().join(STATE_FILE)) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Err(ErrorKind::NothingToResume(clone_path.display().to_string()))?,
+            Err(err) => Err(err)?,
+        };
+        let mut state: State = serde_yaml::from_reader(file)?;
+        state.options.clone_path = repo.workdir().unwrap_or(clone_path).display().to_string();
+        Ok(state)
+    }
+
+    pub fn save(&self, repo: &git2::Repository) -> Result<()> {
+        let file = File::create(repo.path().join(STATE_FILE))?;
+        Ok(serde_yaml::to_writer(file, self)?)
+    }
+
+    pub fn finish(&self, repo: &git2::Repository) -> Result<()> {
+        match fs::remove_file(repo.path().join(STATE_FILE)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => Ok(res?),
+        }
+    }
+}
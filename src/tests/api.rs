@@ -0,0 +1,90 @@
+use reqwest::{Client, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue};
+
+use api::{backoff, is_connection_error, rate_limit_wait, response_date};
+use errors::{Error, ErrorKind};
+use super::{json_response, mock_server, test_api, MockResponse};
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for &(name, value) in pairs {
+        headers.insert(name, HeaderValue::from_static(value));
+    }
+    headers
+}
+
+#[test]
+fn rate_limits() {
+    let exhausted = headers(&[("X-RateLimit-Remaining", "0"), ("X-RateLimit-Reset", "1000030")]);
+    assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &exhausted, 1000000), Some(31), "Didn't wait until the reset");
+    assert_eq!(rate_limit_wait(StatusCode::OK, &exhausted, 1000000), None, "Waited after a successful request");
+
+    let retry_after = headers(&[("Retry-After", "5"), ("X-RateLimit-Remaining", "12")]);
+    assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &retry_after, 1000000), Some(5), "Didn't use Retry-After");
+
+    let forbidden = headers(&[("X-RateLimit-Remaining", "4999"), ("X-RateLimit-Reset", "1000030")]);
+    assert_eq!(rate_limit_wait(StatusCode::FORBIDDEN, &forbidden, 1000000), None, "Treated a permission error as a rate limit");
+}
+
+#[test]
+fn date_header() {
+    assert_eq!(response_date(&headers(&[("Date", "Fri, 26 Jan 2018 19:01:12 GMT")])), Some(1516993272), "Failed to parse the date");
+    assert_eq!(response_date(&headers(&[("Date", "yesterday")])), None, "Parsed an invalid date");
+}
+
+#[test]
+fn backoff_grows() {
+    for attempt in 0..4 {
+        let delay = backoff(attempt).as_millis() as u64;
+        let base = 500 << attempt;
+        assert!(delay >= base / 2 && delay <= base, "Backoff {} for attempt {} is outside {}..={}", delay, attempt, base / 2, base);
+    }
+}
+
+#[test]
+fn connection_errors() {
+    let client = Client::new();
+    let invalid_header = client.get("https://api.github.com/user").header("X-GitHub-OTP", "123\n456").build().unwrap_err();
+    assert!(invalid_header.is_http(), "Expected the invalid header to be an HTTP error");
+    assert!(!is_connection_error(&invalid_header), "Treated a request which couldn't be built as a connection error");
+
+    let refused = client.get("http://127.0.0.1:1/").send().unwrap_err();
+    assert!(is_connection_error(&refused), "Didn't treat a refused connection as a connection error: {}", refused);
+}
+
+fn otp_required() -> MockResponse {
+    MockResponse { path: "/user", status: "401 Unauthorized", headers: "X-GitHub-OTP: required; app\r\n", body: r#"{"message":"Must specify two-factor authentication OTP code."}"# }
+}
+
+#[test]
+fn otp_retry() {
+    let (url, server) = mock_server(vec![
+        otp_required(),
+        json_response("/user", r#"{"login":"octocat"}"#),
+        json_response("/user", r#"{"login":"octocat"}"#),
+    ]);
+    let mut api = test_api(&url);
+    api.set_otp_prompt(|| Ok(Some(" 123456\n".into())));
+    for _ in 0..2 {
+        let res = api.get_authenticated("/user").expect("Request failed");
+        assert_eq!(res.status(), StatusCode::OK, "Request wasn't retried with the code");
+    }
+
+    let requests = server.join().expect("Mock server panicked");
+    assert_eq!(requests[0].header("x-github-otp"), None, "Sent a code before being asked for one");
+    assert_eq!(requests[1].header("x-github-otp"), Some("123456"), "Didn't retry with the code");
+    assert_eq!(requests[2].header("x-github-otp"), Some("123456"), "Didn't reuse the code");
+    assert_eq!(requests[1].header("authorization"), Some("Bearer test-token"), "Retried without the credentials");
+}
+
+#[test]
+fn otp_required_without_prompt() {
+    let (url, server) = mock_server(vec![otp_required()]);
+    let mut api = test_api(&url);
+    api.set_otp_prompt(|| Ok(None));
+    match api.get_authenticated("/user") {
+        Err(Error(ErrorKind::OTPRequired, _)) => {}
+        res => panic!("Expected OTPRequired, got {:?}", res),
+    }
+    server.join().expect("Mock server panicked");
+}
@@ -0,0 +1,49 @@
+use auth::DeviceFlow;
+use reqwest::Client;
+use errors::{Error, ErrorKind};
+use super::{json_response, mock_server};
+
+fn device_flow<'a>(device_code_url: &'a str, token_url: &'a str) -> DeviceFlow<'a> {
+    DeviceFlow {
+        device_code_url: device_code_url,
+        token_url: token_url,
+        client_id: "test-client",
+        scope: "repo",
+    }
+}
+
+const DEVICE_CODE: &str = r#"{"device_code":"test-device-code","user_code":"ABCD-1234","verification_uri":"https://example.com/device","expires_in":900,"interval":0}"#;
+
+#[test]
+fn device_flow_login() {
+    let (url, server) = mock_server(vec![
+        json_response("/device/code", DEVICE_CODE),
+        json_response("/token", r#"{"error":"authorization_pending"}"#),
+        json_response("/token", r#"{"access_token":"test-token","token_type":"bearer","scope":"repo"}"#),
+    ]);
+    let (device_code_url, token_url) = (format!("{}/device/code", url), format!("{}/token", url));
+    let mut shown = None;
+    let token = device_flow(&device_code_url, &token_url).login(&Client::new(), |uri, user_code| shown = Some((uri.to_string(), user_code.to_string())))
+        .expect("Failed to log in");
+    assert_eq!(token.expose(), "test-token", "Returned the wrong token");
+    assert_eq!(shown, Some(("https://example.com/device".into(), "ABCD-1234".into())), "Showed the wrong code");
+
+    let requests = server.join().expect("Mock server panicked");
+    let (device_code, token) = (&requests[0].body, &requests[2].body);
+    assert!(device_code.contains("client_id=test-client") && device_code.contains("scope=repo"), "Bad device code request: {}", device_code);
+    assert!(token.contains("device_code=test-device-code") && token.contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code"), "Bad token request: {}", token);
+}
+
+#[test]
+fn device_flow_denied() {
+    let (url, server) = mock_server(vec![
+        json_response("/device/code", DEVICE_CODE),
+        json_response("/token", r#"{"error":"access_denied"}"#),
+    ]);
+    let (device_code_url, token_url) = (format!("{}/device/code", url), format!("{}/token", url));
+    match device_flow(&device_code_url, &token_url).login(&Client::new(), |_, _| {}) {
+        Err(Error(ErrorKind::AuthorizationDenied, _)) => {}
+        res => panic!("Expected authorization to be denied, got {:?}", res),
+    }
+    server.join().expect("Mock server panicked");
+}
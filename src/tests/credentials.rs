@@ -0,0 +1,32 @@
+use credentials::{gh_hosts_token, StoredToken};
+use repository::Service;
+
+fn token(hosts: &str) -> Option<(Option<String>, String)> {
+    gh_hosts_token(hosts, Service::GitHub).expect("Failed to parse hosts.yml")
+        .map(|StoredToken { username, token }| (username, token.expose().to_string()))
+}
+
+#[test]
+fn gh_hosts_single_account() {
+    let hosts = "github.com:\n    oauth_token: gho_single\n    user: octocat\n    git_protocol: https\n";
+    assert_eq!(token(hosts), Some((Some("octocat".into()), "gho_single".into())), "Didn't read the token");
+    assert_eq!(token("enterprise.example.com:\n    oauth_token: gho_other\n"), None, "Read another host's token");
+    assert_eq!(token("github.com:\n    user: octocat\n"), None, "Found a token for a host without one");
+}
+
+#[test]
+fn gh_hosts_multiple_accounts() {
+    let hosts = "github.com:\n    users:\n        octocat:\n            oauth_token: gho_octocat\n        hubot:\n            oauth_token: gho_hubot\n    git_protocol: https\n    user: hubot\n";
+    assert_eq!(token(hosts), Some((Some("hubot".into()), "gho_hubot".into())), "Didn't read the active account's token");
+
+    let hosts = "github.com:\n    users:\n        octocat:\n        hubot:\n    git_protocol: https\n    user: hubot\n    oauth_token: gho_active\n";
+    assert_eq!(token(hosts), Some((Some("hubot".into()), "gho_active".into())), "Didn't read the host's token");
+
+    let hosts = "github.com:\n    users:\n        octocat:\n    user: octocat\n";
+    assert_eq!(token(hosts), None, "Found a token for an account without one");
+}
+
+#[test]
+fn gh_hosts_malformed() {
+    assert!(gh_hosts_token("github.com: [oauth_token]\n", Service::GitHub).is_err(), "Parsed a malformed hosts.yml");
+}
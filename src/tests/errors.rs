@@ -0,0 +1,61 @@
+use git2::{ErrorClass, ErrorCode};
+use reqwest::Client;
+
+use errors::{exit_code, git_exit_code, Error, ErrorKind, ResultExt};
+use interrupt;
+use repository::Service;
+
+#[test]
+fn exit_codes() {
+    assert_eq!(ErrorKind::FailedToParseRepository.exit_code(), exit_code::OPTIONS, "Wrong exit code for bad options");
+    assert_eq!(ErrorKind::NothingToResume("ghcl".into()).exit_code(), exit_code::OPTIONS, "Wrong exit code for nothing to resume");
+    assert_eq!(ErrorKind::MissingCredentials(Service::GitHub).exit_code(), exit_code::AUTHENTICATION, "Wrong exit code for missing credentials");
+    assert_eq!(ErrorKind::HostKeyMismatch("github.com".into(), "SHA1:abc".into()).exit_code(), exit_code::AUTHENTICATION, "Wrong exit code for a host key mismatch");
+    assert_eq!(ErrorKind::APIError("Not Found".into()).exit_code(), exit_code::API, "Wrong exit code for an API error");
+    assert_eq!(ErrorKind::RateLimited(0).exit_code(), exit_code::API, "Wrong exit code for a rate limit");
+    assert_eq!(ErrorKind::ForkTimedOut(62).exit_code(), exit_code::FORK_TIMED_OUT, "Wrong exit code for a fork timeout");
+    assert_eq!(ErrorKind::ClonePathExists("ghcl".into()).exit_code(), exit_code::FILESYSTEM_CONFLICT, "Wrong exit code for an existing clone path");
+    assert_eq!(ErrorKind::Interrupted.exit_code(), interrupt::EXIT_CODE, "Wrong exit code for an interruption");
+    assert_eq!(ErrorKind::Msg("something".into()).exit_code(), exit_code::OTHER, "Wrong exit code for another error");
+}
+
+#[test]
+fn error_codes() {
+    assert_eq!(ErrorKind::MissingCredentials(Service::GitHub).code(), "missing_credentials", "Wrong code for missing credentials");
+    assert_eq!(ErrorKind::ClonePathExists("ghcl".into()).code(), "clone_path_exists", "Wrong code for an existing clone path");
+    assert_eq!(ErrorKind::Interrupted.code(), "interrupted", "Wrong code for an interruption");
+    assert_eq!(ErrorKind::Msg("something".into()).code(), "other", "Wrong code for another error");
+}
+
+#[test]
+fn reqwest_exit_codes() {
+    let client = Client::new();
+    let refused = client.get("http://127.0.0.1:1/").send().unwrap_err();
+    assert_eq!(ErrorKind::Reqwest(refused).exit_code(), exit_code::NETWORK, "Wrong exit code for a refused connection");
+    let invalid_header = client.get("https://api.github.com/user").header("X-GitHub-OTP", "123\n456").build().unwrap_err();
+    assert_eq!(ErrorKind::Reqwest(invalid_header).exit_code(), exit_code::API, "Used the network exit code for a request which couldn't be built");
+}
+
+#[test]
+fn git_exit_codes() {
+    assert_eq!(git_exit_code(ErrorCode::Auth, ErrorClass::Net), exit_code::AUTHENTICATION, "Wrong exit code for a git authentication error");
+    assert_eq!(git_exit_code(ErrorCode::Exists, ErrorClass::Repository), exit_code::FILESYSTEM_CONFLICT, "Wrong exit code for an existing repository");
+    assert_eq!(git_exit_code(ErrorCode::GenericError, ErrorClass::Net), exit_code::NETWORK, "Wrong exit code for a git network error");
+    assert_eq!(git_exit_code(ErrorCode::GenericError, ErrorClass::Ssh), exit_code::NETWORK, "Wrong exit code for an SSH error");
+    assert_eq!(git_exit_code(ErrorCode::NotFound, ErrorClass::Reference), exit_code::OTHER, "Wrong exit code for another git error");
+}
+
+#[test]
+fn specific_kind() {
+    let res: Result<(), Error> = Err(ErrorKind::MissingCredentials(Service::GitHub).into());
+    let err = res.chain_err(|| "Failed to fork repository").chain_err(|| "Failed to get options").unwrap_err();
+    match *err.kind() {
+        ErrorKind::Msg(_) => {}
+        ref kind => panic!("Expected a context message, got {:?}", kind),
+    }
+    match *err.specific_kind() {
+        ErrorKind::MissingCredentials(Service::GitHub) => {}
+        ref kind => panic!("Expected missing credentials, got {:?}", kind),
+    }
+    assert_eq!(err.specific_kind().exit_code(), exit_code::AUTHENTICATION, "Didn't see through the context messages for the exit code");
+}
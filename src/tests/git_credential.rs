@@ -0,0 +1,14 @@
+use git_credential::{parse_credential, GitCredential};
+use secrets::Secret;
+
+#[test]
+fn parse_fill_output() {
+    assert_eq!(parse_credential("protocol=https\nhost=github.com\nusername=octocat\npassword=gho_abc=def\n").expect("Failed to parse credential"), GitCredential {
+        protocol: "https".into(),
+        host: "github.com".into(),
+        username: Some("octocat".into()),
+        password: Secret::new("gho_abc=def".into()),
+    }, "Failed to parse a full credential");
+
+    assert_eq!(parse_credential("protocol=https\nhost=github.com\n"), None, "Parsed a credential without a password");
+}
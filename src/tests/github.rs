@@ -0,0 +1,29 @@
+use serde_json;
+
+use github::{ApiError, Repo};
+
+#[test]
+fn parse_repo() {
+    let repo: Repo = serde_json::from_str(r#"{
+        "id": 1, "full_name": "user/ghcl", "fork": true,
+        "ssh_url": "git@github.com:user/ghcl.git", "clone_url": "https://github.com/user/ghcl.git",
+        "default_branch": "main", "created_at": "2018-01-26T19:01:12Z", "permissions": { "admin": true, "push": true, "pull": true },
+        "parent": {
+            "full_name": "PlasmaPower/ghcl", "ssh_url": "git@github.com:PlasmaPower/ghcl.git",
+            "clone_url": "https://github.com/PlasmaPower/ghcl.git", "default_branch": "master"
+        }
+    }"#).expect("Failed to parse repository");
+    assert!(repo.is_fork_of("plasmapower/ghcl"), "Failed to recognize the parent repository");
+    assert_eq!(repo.created_at_unix(), Some(1516993272), "Failed to parse the creation time");
+    assert!(repo.permissions.expect("Failed to parse permissions").push, "Failed to parse the push permission");
+}
+
+#[test]
+fn api_error_message() {
+    let error: ApiError = serde_json::from_str(r#"{
+        "message": "Validation Failed",
+        "errors": [{ "resource": "Fork", "code": "custom", "message": "organization is not accessible" }, "name is too long"],
+        "documentation_url": "https://docs.github.com/rest/repos/forks"
+    }"#).expect("Failed to parse error");
+    assert_eq!(error.to_string(), "Validation Failed (organization is not accessible; name is too long) (see https://docs.github.com/rest/repos/forks)", "Wrong error message");
+}
@@ -0,0 +1,27 @@
+use host_keys::{check_known_hosts, hmac_sha1, Known};
+
+const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+const KEY_SHA1: [u8; 20] = [0xe9, 0x61, 0x9e, 0x2e, 0xd5, 0x6c, 0x2f, 0x2a, 0x71, 0x72, 0x9d, 0xb8, 0x0b, 0xac, 0xc2, 0xce, 0x9c, 0xcc, 0xe8, 0xd4];
+
+#[test]
+fn hmac() {
+    let expected = [0xb6, 0x17, 0x31, 0x86, 0x55, 0x05, 0x72, 0x64, 0xe2, 0x8b, 0xc0, 0xb6, 0xfb, 0x37, 0x8c, 0x8e, 0xf1, 0x46, 0xbe, 0x00];
+    assert_eq!(hmac_sha1(&[0x0b; 20], b"Hi There"), expected, "Failed RFC 2202 HMAC-SHA1 test case 1");
+}
+
+#[test]
+fn known_hosts() {
+    let plain = format!("git.example.com,10.0.0.1 ssh-ed25519 {}\n", KEY);
+    assert_eq!(check_known_hosts(&plain, "git.example.com", &KEY_SHA1), Known::Trusted, "Failed to find a plain host");
+    assert_eq!(check_known_hosts(&plain, "other.example.com", &KEY_SHA1), Known::Unknown, "Matched the wrong host");
+    assert_eq!(check_known_hosts(&plain, "git.example.com", &[0; 20]), Known::Mismatch, "Failed to detect a different key");
+
+    let hashed = format!("|1|AAECAwQFBgcICQoLDA0ODxAREhM=|5DHGyoq+K/vX4lJK0U2p7IteoFw= ssh-ed25519 {}\n", KEY);
+    assert_eq!(check_known_hosts(&hashed, "git.example.com", &KEY_SHA1), Known::Trusted, "Failed to find a hashed host");
+
+    let revoked = format!("{}@revoked * ssh-ed25519 {}\n", plain, KEY);
+    assert_eq!(check_known_hosts(&revoked, "git.example.com", &KEY_SHA1), Known::Revoked, "Failed to detect a revoked key");
+
+    assert_eq!(check_known_hosts("git.example.com SHA1:6WGeLtVsLypxcp24C6zCzpzM6NQ\n", "git.example.com", &KEY_SHA1), Known::Trusted,
+        "Failed to find a key trusted by ghcl");
+}
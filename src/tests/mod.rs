@@ -1 +1,109 @@
 mod repository;
+mod api;
+mod github;
+mod auth;
+mod secrets;
+mod git_credential;
+mod credentials;
+mod git_operations;
+mod ssh_config;
+mod host_keys;
+mod network;
+mod options;
+mod errors;
+mod state;
+
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+
+use api::Api;
+use network::Network;
+use options::{Authentication, Credentials};
+use repository::Service;
+use secrets::Secret;
+
+/// An empty directory for a test, under the system's temporary directory
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ghcl-test-{}-{}", process::id(), name));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).expect("Failed to remove old test directory");
+    }
+    fs::create_dir_all(&dir).expect("Failed to create test directory");
+    dir
+}
+
+/// Authenticated with a token, without retries
+fn test_api(url: &str) -> Api {
+    let credentials = Credentials::known(Authentication::Token { username: None, token: Secret::new("test-token".into()) });
+    Api::with_url(Service::GitHub, url, &Network::default(), Rc::new(credentials), 0, true).expect("Failed to create API client")
+}
+
+/// A canned response for `mock_server`, to a request for `path`
+struct MockResponse {
+    path: &'static str,
+    status: &'static str,
+    /// Extra header lines, each ending in `\r\n`
+    headers: &'static str,
+    body: &'static str,
+}
+
+fn json_response(path: &'static str, body: &'static str) -> MockResponse {
+    MockResponse { path: path, status: "200 OK", headers: "", body: body }
+}
+
+/// A request received by `mock_server`
+struct MockRequest {
+    /// With the names lowercased
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl MockRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|&&(ref key, _)| key == name).map(|&(_, ref value)| value.as_str())
+    }
+}
+
+/// Serves one canned response per connection, in order, checking each request's path.
+/// The thread returns the requests.
+fn mock_server(responses: Vec<MockResponse>) -> (String, thread::JoinHandle<Vec<MockRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server");
+    let url = format!("http://{}", listener.local_addr().expect("Failed to get mock server address"));
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (stream, _) = listener.accept().expect("Failed to accept connection");
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).expect("Failed to read request line");
+            assert_eq!(request_line.split_whitespace().nth(1), Some(response.path), "Request made to the wrong path");
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).expect("Failed to read header");
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some(i) = line.find(':') {
+                    headers.push((line[..i].trim().to_lowercase(), line[i + 1..].trim().to_string()));
+                }
+            }
+            let content_length = headers.iter().find(|&&(ref name, _)| name == "content-length")
+                .map_or(0, |&(_, ref value)| value.parse().expect("Failed to parse content length"));
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).expect("Failed to read body");
+            requests.push(MockRequest { headers: headers, body: String::from_utf8(body).expect("Body was not UTF-8") });
+            write!(reader.into_inner(), "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}",
+                response.status, response.body.len(), response.headers, response.body)
+                .expect("Failed to write response");
+        }
+        requests
+    });
+    (url, handle)
+}
@@ -0,0 +1,17 @@
+use network::{no_proxy_matches, Network};
+
+#[test]
+fn no_proxy() {
+    assert!(no_proxy_matches("localhost,.example.com", "api.example.com"), "Failed to match a subdomain");
+    assert!(no_proxy_matches("localhost, example.com:443", "example.com"), "Failed to match a host with a port");
+    assert!(!no_proxy_matches("example.com", "notexample.com"), "Matched a different domain with the same suffix");
+    assert!(no_proxy_matches("*", "github.com"), "Failed to match a wildcard");
+
+    let network = Network {
+        proxy: Some("http://proxy.example.com:8080".into()),
+        no_proxy: Some("internal.example.com".into()),
+        ca_bundle: None,
+    };
+    assert_eq!(network.proxy_for("api.github.com"), Some("http://proxy.example.com:8080"), "Didn't use the proxy");
+    assert_eq!(network.proxy_for("git.internal.example.com"), None, "Used the proxy for a NO_PROXY host");
+}
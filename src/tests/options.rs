@@ -0,0 +1,227 @@
+use std::path::Path;
+use std::collections::HashMap;
+
+use serde_yaml;
+
+use options::{resolve_credentials, Authentication, Config, CredentialLookup, CredentialSources, PartialAuthentication};
+use credentials::StoredToken;
+use git_credential::GitCredential;
+use repository::Service;
+use secrets::Secret;
+use errors::*;
+
+const CONFIG: &str = "
+organization: personal
+authentication:
+  github:
+    keyring: true
+profiles:
+  oss:
+    owners: [rust-lang]
+    organization: oss
+  work:
+    owners: [myEmployer]
+    directories: [/home/me/work]
+    organization: myEmployer
+    authentication:
+      github:
+        keyring: true
+";
+
+#[test]
+fn find_profile() {
+    let config: Config = serde_yaml::from_str(CONFIG).expect("Failed to parse config");
+    assert_eq!(config.find_profile("MyEmployer", Path::new("/tmp/repo")), Some("work".into()), "Failed to match an owner");
+    assert_eq!(config.find_profile("someone", Path::new("/home/me/work/repo")), Some("work".into()), "Failed to match a directory");
+    assert_eq!(config.find_profile("rust-lang", Path::new("/home/me/work/repo")), Some("oss".into()), "Didn't prefer the owner to the directory");
+    assert_eq!(config.find_profile("someone", Path::new("/home/me/workshop")), None, "Matched a directory with the same prefix");
+}
+
+#[test]
+fn apply_profile() {
+    let mut config: Config = serde_yaml::from_str(CONFIG).expect("Failed to parse config");
+    config.apply_profile("oss").expect("Failed to apply profile");
+    assert_eq!(config.organization, Some("oss".into()), "Didn't override the organization");
+    assert_eq!(config.authentication[&Service::GitHub].profile, None, "Used the profile's account for the config's credentials");
+
+    let mut config: Config = serde_yaml::from_str(CONFIG).expect("Failed to parse config");
+    config.apply_profile("work").expect("Failed to apply profile");
+    assert_eq!(config.authentication[&Service::GitHub].profile, Some("work".into()), "Didn't use the profile's account for its credentials");
+
+    let mut config: Config = serde_yaml::from_str(CONFIG).expect("Failed to parse config");
+    assert!(config.apply_profile("missing").is_err(), "Applied a profile which doesn't exist");
+}
+
+#[derive(Default)]
+struct TestLookup {
+    env: HashMap<&'static str, &'static str>,
+    stored: Option<&'static str>,
+    gh_cli: Option<&'static str>,
+    git_credential: Option<&'static str>,
+}
+
+fn stored_token(token: Option<&'static str>) -> Result<Option<StoredToken>> {
+    Ok(token.map(|token| StoredToken { username: None, token: Secret::new(token.into()) }))
+}
+
+impl CredentialLookup for TestLookup {
+    fn env(&self, names: &[&str]) -> Option<String> {
+        names.iter().filter_map(|name| self.env.iter().find(|&(key, _)| key == name)).next().map(|(_, value)| value.to_string())
+    }
+
+    fn stored_token(&self, _: Service, _: Option<&str>) -> Result<Option<StoredToken>> {
+        stored_token(self.stored)
+    }
+
+    fn gh_cli_token(&self, _: Service) -> Result<Option<StoredToken>> {
+        stored_token(self.gh_cli)
+    }
+
+    fn git_credential(&self, host: &str) -> Option<GitCredential> {
+        self.git_credential.map(|password| GitCredential {
+            protocol: "https".into(),
+            host: host.into(),
+            username: None,
+            password: Secret::new(password.into()),
+        })
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn prompt_token(&self) -> Result<Secret> {
+        Ok(Secret::new("prompt".into()))
+    }
+}
+
+/// The credential sources, and the config lines to set up, before they're resolved
+struct Setup {
+    sources: CredentialSources,
+    config: Vec<&'static str>,
+    lookup: TestLookup,
+}
+
+fn cli_token(setup: &mut Setup) {
+    setup.sources.token = Some(Secret::new("--token".into()));
+}
+
+fn cli_password(setup: &mut Setup) {
+    setup.sources.password = Some(Secret::new("--password".into()));
+}
+
+fn ghcl_token_env(setup: &mut Setup) {
+    setup.lookup.env.insert("GHCL_TOKEN", "GHCL_TOKEN");
+}
+
+fn gh_token_env(setup: &mut Setup) {
+    setup.lookup.env.insert("GH_TOKEN", "GH_TOKEN");
+}
+
+fn github_token_env(setup: &mut Setup) {
+    setup.lookup.env.insert("GITHUB_TOKEN", "GITHUB_TOKEN");
+}
+
+fn config_token(setup: &mut Setup) {
+    setup.config.push("token: config token");
+}
+
+fn config_password_command(setup: &mut Setup) {
+    setup.config.push("password_command: echo password_command");
+}
+
+fn config_password(setup: &mut Setup) {
+    setup.config.push("password: config password");
+}
+
+fn credential_store(setup: &mut Setup) {
+    setup.lookup.stored = Some("credential store");
+}
+
+fn gh_cli(setup: &mut Setup) {
+    setup.lookup.gh_cli = Some("gh CLI");
+}
+
+fn git_credential(setup: &mut Setup) {
+    setup.lookup.git_credential = Some("git credential");
+}
+
+fn prompt(_: &mut Setup) {}
+
+/// Each credential source, in order of precedence, with the secret it sets up
+const SOURCES: &[(&str, fn(&mut Setup))] = &[
+    ("--token", cli_token),
+    ("--password", cli_password),
+    ("GHCL_TOKEN", ghcl_token_env),
+    ("GH_TOKEN", gh_token_env),
+    ("GITHUB_TOKEN", github_token_env),
+    ("config token", config_token),
+    ("password_command", config_password_command),
+    ("config password", config_password),
+    ("credential store", credential_store),
+    ("gh CLI", gh_cli),
+    ("git credential", git_credential),
+    ("prompt", prompt),
+];
+
+fn resolve(setups: &[fn(&mut Setup)]) -> Result<Authentication> {
+    let mut setup = Setup {
+        sources: CredentialSources {
+            service: Service::GitHub,
+            profile: None,
+            username: Some("me".into()),
+            token: None,
+            password: None,
+            config: None,
+        },
+        config: Vec::new(),
+        lookup: TestLookup::default(),
+    };
+    for f in setups {
+        f(&mut setup);
+    }
+    if !setup.config.is_empty() {
+        let config: PartialAuthentication = serde_yaml::from_str(&setup.config.join("\n")).expect("Failed to parse authentication config");
+        setup.sources.config = Some(config);
+    }
+    resolve_credentials(setup.sources, true, &setup.lookup).map(|(authentication, _)| authentication)
+}
+
+fn secret(authentication: &Authentication) -> &str {
+    match *authentication {
+        Authentication::Token { ref token, .. } => token.expose(),
+        Authentication::Basic { ref password, .. } => password.expose(),
+    }
+}
+
+#[test]
+fn credential_precedence() {
+    for pair in SOURCES.windows(2) {
+        let ((higher, set_higher), (lower, set_lower)) = (pair[0], pair[1]);
+        let authentication = resolve(&[set_lower, set_higher]).expect("Failed to resolve credentials");
+        assert_eq!(secret(&authentication), higher, "Preferred {} to {}", lower, higher);
+    }
+}
+
+#[test]
+fn credential_sources() {
+    for &(name, set) in SOURCES {
+        let authentication = resolve(&[set]).expect("Failed to resolve credentials");
+        assert_eq!(secret(&authentication), name, "Didn't use {}", name);
+    }
+}
+
+#[test]
+fn password_command_before_keyring() {
+    let config: PartialAuthentication = serde_yaml::from_str("keyring: true\npassword_command: echo password_command").expect("Failed to parse authentication config");
+    let sources = CredentialSources {
+        service: Service::GitHub,
+        profile: None,
+        username: None,
+        token: None,
+        password: None,
+        config: Some(config),
+    };
+    let (authentication, _) = resolve_credentials(sources, false, &TestLookup::default()).expect("Failed to resolve credentials");
+    assert_eq!(secret(&authentication), "password_command", "Read the keyring before running the password command");
+}
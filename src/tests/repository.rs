@@ -1,4 +1,9 @@
-use repository::{Repository, Service};
+use reqwest::Client;
+
+use github;
+use repository::{github_res, GitProtocol, Repository, Service};
+use errors::{Error, ErrorKind};
+use super::{mock_server, test_api, MockResponse};
 
 #[test]
 fn from_arg_string() {
@@ -20,3 +25,38 @@ fn from_arg_string() {
         name: "bar".into(),
     }, "Failed to parse detailed GitHub URL arg string");
 }
+
+#[test]
+fn non_json_error_response() {
+    let (url, server) = mock_server(vec![MockResponse {
+        path: "/user",
+        status: "502 Bad Gateway",
+        headers: "",
+        body: "<html><body>Bad gateway</body></html>",
+    }]);
+    let res = Client::new().get(&format!("{}/user", url)).send().expect("Failed to send request");
+    match github_res::<github::User>(res) {
+        Err(Error(ErrorKind::APIError(ref message), _)) => assert!(message.starts_with("502 Bad Gateway <html>"), "Lost the status: {}", message),
+        res => panic!("Expected an API error, got {:?}", res),
+    }
+    server.join().expect("Mock server panicked");
+}
+
+#[test]
+fn fork_without_repo_scope() {
+    let (url, server) = mock_server(vec![MockResponse {
+        path: "/user",
+        status: "200 OK",
+        headers: "X-OAuth-Scopes: gist, read:org\r\n",
+        body: r#"{"login":"octocat"}"#,
+    }]);
+    let repository = Repository { service: Service::GitHub, user: "foo".into(), name: "bar".into() };
+    match repository.fork(&test_api(&url), None, GitProtocol::HTTPS) {
+        Err(ref err) => match *err.specific_kind() {
+            ErrorKind::InsufficientPermissions(_) => {}
+            ref kind => panic!("Expected InsufficientPermissions, got {:?}", kind),
+        },
+        Ok(fork) => panic!("Forked without the repo scope: {}", fork.git_url),
+    }
+    server.join().expect("Mock server panicked");
+}
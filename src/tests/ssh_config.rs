@@ -0,0 +1,48 @@
+use ssh_config::{parse, with_host, HostConfig, SshUrl};
+
+#[test]
+fn parse_ssh_urls() {
+    assert_eq!(SshUrl::parse("git@github.com:foo/bar.git").expect("Failed to parse scp-like URL"), SshUrl {
+        user: Some("git"),
+        host: "github.com",
+        port: None,
+        path: "foo/bar.git",
+    }, "Failed to parse scp-like URL");
+
+    assert_eq!(SshUrl::parse("ssh://git@github.com:2222/foo/bar.git").expect("Failed to parse ssh URL"), SshUrl {
+        user: Some("git"),
+        host: "github.com",
+        port: Some("2222"),
+        path: "foo/bar.git",
+    }, "Failed to parse ssh URL with a port");
+
+    assert_eq!(SshUrl::parse("https://github.com/foo/bar.git"), None, "Parsed an HTTPS URL as SSH");
+    assert_eq!(with_host("git@github.com:foo/bar.git", "github-work"), "git@github-work:foo/bar.git", "Failed to replace the host");
+}
+
+#[test]
+fn parse_host_config() {
+    let config = "\
+IdentityFile ~/.ssh/default
+
+Host github-work
+    HostName github.com
+    IdentityFile ~/.ssh/work
+    User git
+
+Host *.example.com !internal.example.com
+    Port 2222
+
+Host *
+    HostName ignored.example.com
+";
+    assert_eq!(parse(config, "github-work"), HostConfig {
+        host_name: Some("github.com".into()),
+        user: Some("git".into()),
+        port: None,
+        identity_files: vec!["~/.ssh/default".into(), "~/.ssh/work".into()],
+    }, "Failed to parse settings for an alias");
+
+    assert_eq!(parse(config, "git.example.com").port, Some("2222".into()), "Failed to match a wildcard host");
+    assert_eq!(parse(config, "internal.example.com").port, None, "Matched a negated host");
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::rc::Rc;
+
+use git_operations::init_repo;
+use network::Network;
+use options::{Credentials, Options};
+use repository::{GitProtocol, Repository, Service};
+use state::State;
+use errors::*;
+use super::temp_dir;
+
+fn options(clone_path: String) -> Options {
+    Options {
+        repository: Repository { service: Service::GitHub, user: "user".into(), name: "repo".into() },
+        profile: Some("work".into()),
+        organization: Some("org".into()),
+        track_upstream: true,
+        setup_upstream: false,
+        remote_name: "parent".into(),
+        origin_protocol: GitProtocol::SSH,
+        upstream_protocol: GitProtocol::HTTPS,
+        ssh_key: Some("~/.ssh/id_work".into()),
+        ssh_host: None,
+        credentials: Rc::new(Credentials::default()),
+        network: Network::default(),
+        clone_path: clone_path,
+        quiet: true,
+        fork_timeout: 42,
+        api_retries: 3,
+        dry_run: false,
+    }
+}
+
+#[test]
+fn state_round_trip() {
+    let dir = temp_dir("state-round-trip");
+    let clone_path = dir.join("repo");
+    let repo = init_repo("https://github.com/user/repo.git", &clone_path).expect("Failed to create repository");
+    let mut state = State::new(options(clone_path.display().to_string()));
+    state.fork_git_url = Some("git@github.com:me/repo.git".into());
+    state.cloned = true;
+    state.save(&repo).expect("Failed to save state");
+
+    let loaded = State::load(&clone_path).expect("Failed to load state");
+    assert_eq!(loaded.fork_git_url, state.fork_git_url, "Fork URL changed");
+    assert_eq!((loaded.cloned, loaded.upstream_added, loaded.upstream_tracked), (true, false, false), "Progress changed");
+    let options = loaded.options;
+    assert_eq!(options.repository, state.options.repository, "Repository changed");
+    assert_eq!(options.profile, Some("work".into()), "Profile changed");
+    assert_eq!(options.organization, Some("org".into()), "Organization changed");
+    assert_eq!((options.track_upstream, options.setup_upstream), (true, false), "Upstream options changed");
+    assert_eq!(options.remote_name, "parent", "Remote name changed");
+    assert_eq!((options.origin_protocol, options.upstream_protocol), (GitProtocol::SSH, GitProtocol::HTTPS), "Protocols changed");
+    assert_eq!(options.ssh_key, Some("~/.ssh/id_work".into()), "SSH key changed");
+    assert_eq!((options.quiet, options.fork_timeout), (true, 42), "Output options changed");
+    assert_eq!(fs::canonicalize(&options.clone_path).ok(), fs::canonicalize(&clone_path).ok(), "Clone path changed");
+
+    state.finish(&repo).expect("Failed to remove state");
+    match State::load(&clone_path) {
+        Err(Error(ErrorKind::NothingToResume(_), _)) => {}
+        res => panic!("Expected NothingToResume after finishing, got {:?}", res),
+    }
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn load_state_from_subdirectory() {
+    let dir = temp_dir("state-subdirectory");
+    let clone_path = dir.join("repo");
+    let repo = init_repo("https://github.com/user/repo.git", &clone_path).expect("Failed to create repository");
+    State::new(options(clone_path.display().to_string())).save(&repo).expect("Failed to save state");
+    let subdirectory = clone_path.join("src").join("nested");
+    fs::create_dir_all(&subdirectory).expect("Failed to create subdirectory");
+
+    let loaded = State::load(&subdirectory).expect("Failed to load state from a subdirectory");
+    assert_eq!(fs::canonicalize(&loaded.options.clone_path).ok(), fs::canonicalize(&clone_path).ok(),
+        "Clone path isn't the repository's working directory");
+    fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn nothing_to_resume() {
+    let dir = temp_dir("state-missing");
+    let clone_path = dir.join("repo");
+    init_repo("https://github.com/user/repo.git", &clone_path).expect("Failed to create repository");
+    match State::load(&clone_path) {
+        Err(Error(ErrorKind::NothingToResume(_), _)) => {}
+        res => panic!("Expected NothingToResume without a state file, got {:?}", res),
+    }
+    fs::remove_dir_all(dir).ok();
+}
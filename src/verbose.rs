@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use secrets;
+
+pub const DEBUG: usize = 1;
+pub const TRACE: usize = 2;
+
+static VERBOSITY: AtomicUsize = AtomicUsize::new(0);
+
+macro_rules! verbose {
+    ($level:expr, $($arg:tt)*) => {
+        if ::verbose::enabled($level) {
+            ::verbose::print(&format!($($arg)*));
+        }
+    };
+}
+
+pub fn set_verbosity(occurrences: u64) {
+    VERBOSITY.store(occurrences as usize, Ordering::SeqCst);
+}
+
+pub fn enabled(level: usize) -> bool {
+    VERBOSITY.load(Ordering::SeqCst) >= level
+}
+
+pub fn print(message: &str) {
+    writeln!(io::stderr(), "[ghcl] {}", secrets::scrub(message)).ok();
+}